@@ -0,0 +1,688 @@
+//! An assembler for the Cowgod syntax printed by the disassembler.
+//!
+//! Each line holds an optional `label:`, then an instruction, a data
+//! directive or an Octo style directive, and an optional `;` comment:
+//!
+//! ```text
+//! :alias counter V3
+//! :const SPEED 2
+//! :include "sprites.asm"
+//! loop:   ADD counter, SPEED
+//!         JP loop
+//! table:  db 1, 2, 0x30 + 4
+//!         dw 0x1234
+//! ```
+//!
+//! Mnemonics and register names are case insensitive, symbols are not.
+//! Operands may add or subtract numbers (decimal, `0x` or `0b`), constants and
+//! labels. Labels can be used before they are defined, constants can't.
+
+use crate::{encode, Instruction};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+/// The address programs are assembled for.
+pub const DEFAULT_ORIGIN: u16 = 0x200;
+
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+const MNEMONICS: &[&str] = &[
+    "CLS", "RET", "SYS", "SCD", "SCU", "SCR", "SCL", "EXIT", "LOW", "HIGH", "JP", "CALL", "SE",
+    "SNE", "SAVE", "LOAD", "LD", "ADD", "OR", "AND", "XOR", "SUB", "SHR", "SUBN", "SHL", "RND",
+    "DRW", "SKP", "SKNP", "PLANE", "AUDIO", "PITCH", "DB", "DW",
+];
+
+/// An error in a source file, with the line it was found on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    pub file: String,
+    /// 1-based, 0 if the error isn't about a specific line.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles `source`. Included files are looked up relative to the current
+/// directory.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let mut asm = Assembler::new();
+    asm.parse(source, Rc::from("<input>"), Path::new(""), 0)?;
+    asm.finish()
+}
+
+/// Assembles the file at `path`. Included files are looked up relative to the
+/// file including them.
+pub fn assemble_file<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, AsmError> {
+    let path = path.as_ref();
+    let name: Rc<str> = Rc::from(path.display().to_string());
+    let source = fs::read_to_string(path).map_err(|e| AsmError {
+        file: name.to_string(),
+        line: 0,
+        message: e.to_string(),
+    })?;
+    let mut asm = Assembler::new();
+    asm.parse(&source, name, path.parent().unwrap_or(Path::new("")), 0)?;
+    asm.finish()
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    Number(i64),
+    Symbol(String),
+}
+
+/// Terms to add up, each with whether it is subtracted.
+type Expr = Vec<(bool, Term)>;
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Reg(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Expr),
+    Value(Expr),
+}
+
+struct Statement {
+    file: Rc<str>,
+    line: usize,
+    addr: u16,
+    mnemonic: String,
+    operands: Vec<Operand>,
+}
+
+struct Assembler {
+    statements: Vec<Statement>,
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, i64>,
+    aliases: HashMap<String, u8>,
+    // next free address, may go one past the end of memory
+    addr: u32,
+}
+
+impl Assembler {
+    fn new() -> Assembler {
+        Assembler {
+            statements: vec![],
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            addr: DEFAULT_ORIGIN as u32,
+        }
+    }
+
+    /// First pass: records labels, constants and aliases, and the address of
+    /// every statement.
+    fn parse(
+        &mut self,
+        source: &str,
+        file: Rc<str>,
+        dir: &Path,
+        depth: usize,
+    ) -> Result<(), AsmError> {
+        for (n, line) in source.lines().enumerate() {
+            let err = |message: String| AsmError {
+                file: file.to_string(),
+                line: n + 1,
+                message,
+            };
+            let mut line = strip_comment(line).trim();
+            if line.is_empty() {
+                continue;
+            }
+            if line.starts_with(':') {
+                self.directive(line, dir, depth).map_err(|e| match e {
+                    DirectiveError::Nested(e) => e,
+                    DirectiveError::Message(message) => err(message),
+                })?;
+                continue;
+            }
+            let first = line.split_whitespace().next().unwrap_or("");
+            if let Some(label) = first.strip_suffix(':') {
+                self.define_label(label).map_err(err)?;
+                line = line[first.len()..].trim_start();
+                if line.is_empty() {
+                    continue;
+                }
+            }
+            let (mnemonic, rest) = match line.find(char::is_whitespace) {
+                Some(i) => (&line[..i], line[i..].trim()),
+                None => (line, ""),
+            };
+            let operands = if rest.is_empty() {
+                vec![]
+            } else {
+                rest.split(',')
+                    .map(|op| self.operand(op.trim()))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(err)?
+            };
+            let size = match (mnemonic.to_uppercase().as_str(), &operands[..]) {
+                ("DB", ops) => ops.len(),
+                ("DW", ops) => 2 * ops.len(),
+                ("LD", [_, Operand::Long(_)]) => 4,
+                _ => 2,
+            };
+            if self.addr as usize + size > 0x10000 {
+                return Err(err("program doesn't fit in memory".to_string()));
+            }
+            self.statements.push(Statement {
+                file: file.clone(),
+                line: n + 1,
+                addr: self.addr as u16,
+                mnemonic: mnemonic.to_string(),
+                operands,
+            });
+            self.addr += size as u32;
+        }
+        Ok(())
+    }
+
+    fn directive(&mut self, line: &str, dir: &Path, depth: usize) -> Result<(), DirectiveError> {
+        let mut words = line.splitn(2, char::is_whitespace);
+        let name = words.next().unwrap_or("");
+        let rest = words.next().unwrap_or("").trim();
+        match name {
+            ":alias" => {
+                let (alias, reg) = split_name(rest)?;
+                match register(reg) {
+                    Some(r) => {
+                        self.aliases.insert(alias.to_string(), r);
+                        Ok(())
+                    }
+                    None => Err(DirectiveError::Message(format!(
+                        "'{}' is not a register",
+                        reg
+                    ))),
+                }
+            }
+            ":const" => {
+                let (constant, value) = split_name(rest)?;
+                if self.labels.contains_key(constant) || self.consts.contains_key(constant) {
+                    return Err(DirectiveError::Message(format!(
+                        "'{}' is already defined",
+                        constant
+                    )));
+                }
+                let value = self.eval(&parse_expr(value)?)?;
+                self.consts.insert(constant.to_string(), value);
+                Ok(())
+            }
+            ":include" => {
+                let name = rest
+                    .strip_prefix('"')
+                    .and_then(|s| s.strip_suffix('"'))
+                    .ok_or_else(|| {
+                        DirectiveError::Message("expected a quoted file name".to_string())
+                    })?;
+                if depth >= MAX_INCLUDE_DEPTH {
+                    return Err(DirectiveError::Message(
+                        "includes are nested too deeply".to_string(),
+                    ));
+                }
+                let path: PathBuf = dir.join(name);
+                let source = fs::read_to_string(&path).map_err(|e| {
+                    DirectiveError::Message(format!("can't include '{}': {}", path.display(), e))
+                })?;
+                let file = Rc::from(path.display().to_string());
+                self.parse(&source, file, path.parent().unwrap_or(dir), depth + 1)
+                    .map_err(DirectiveError::Nested)
+            }
+            _ => Err(DirectiveError::Message(format!(
+                "unknown directive '{}'",
+                name
+            ))),
+        }
+    }
+
+    fn define_label(&mut self, label: &str) -> Result<(), String> {
+        if !is_identifier(label) {
+            return Err(format!("invalid label '{}'", label));
+        }
+        if self.labels.contains_key(label) || self.consts.contains_key(label) {
+            return Err(format!("'{}' is already defined", label));
+        }
+        self.labels.insert(label.to_string(), self.addr as u16);
+        Ok(())
+    }
+
+    fn operand(&self, op: &str) -> Result<Operand, String> {
+        let operand = match op.to_uppercase().as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::Dt,
+            "ST" => Operand::St,
+            "K" => Operand::K,
+            "F" => Operand::F,
+            "HF" => Operand::Hf,
+            "B" => Operand::B,
+            "R" => Operand::R,
+            upper if upper.starts_with("LONG ") => Operand::Long(parse_expr(&op[5..])?),
+            _ => match register(op).or_else(|| self.aliases.get(op).copied()) {
+                Some(r) => Operand::Reg(r),
+                None => Operand::Value(parse_expr(op)?),
+            },
+        };
+        Ok(operand)
+    }
+
+    fn eval(&self, expr: &Expr) -> Result<i64, String> {
+        let mut total: i64 = 0;
+        for (negative, term) in expr {
+            let value = match term {
+                Term::Number(n) => *n,
+                Term::Symbol(s) => match self.consts.get(s) {
+                    Some(value) => *value,
+                    None => match self.labels.get(s) {
+                        Some(addr) => *addr as i64,
+                        None => return Err(format!("unknown symbol '{}'", s)),
+                    },
+                },
+            };
+            total = if *negative {
+                total.checked_sub(value)
+            } else {
+                total.checked_add(value)
+            }
+            .ok_or_else(|| "value out of range".to_string())?;
+        }
+        Ok(total)
+    }
+
+    fn eval_range(&self, expr: &Expr, min: i64, max: i64) -> Result<i64, String> {
+        let value = self.eval(expr)?;
+        if value < min || value > max {
+            return Err(format!("value {} out of range {}..{}", value, min, max));
+        }
+        Ok(value)
+    }
+
+    fn addr(&self, expr: &Expr) -> Result<u16, String> {
+        Ok(self.eval_range(expr, 0, 0xFFF)? as u16)
+    }
+
+    /// Bytes may be written signed.
+    fn byte(&self, expr: &Expr) -> Result<u8, String> {
+        Ok(self.eval_range(expr, -128, 255)? as u8)
+    }
+
+    fn nibble(&self, expr: &Expr) -> Result<u8, String> {
+        Ok(self.eval_range(expr, 0, 15)? as u8)
+    }
+
+    /// Second pass: generates the code now that all labels are known.
+    fn finish(self) -> Result<Vec<u8>, AsmError> {
+        let mut rom = vec![];
+        for statement in &self.statements {
+            let bytes = self.statement(statement).map_err(|message| AsmError {
+                file: statement.file.to_string(),
+                line: statement.line,
+                message,
+            })?;
+            debug_assert_eq!(DEFAULT_ORIGIN as usize + rom.len(), statement.addr as usize);
+            rom.extend(bytes);
+        }
+        Ok(rom)
+    }
+
+    fn statement(&self, statement: &Statement) -> Result<Vec<u8>, String> {
+        use Instruction::*;
+        use Operand::{Long, Reg, Value};
+        let mnemonic = statement.mnemonic.to_uppercase();
+        let ops = &statement.operands[..];
+        let instruction = match (mnemonic.as_str(), ops) {
+            ("DB", ops) => {
+                return ops
+                    .iter()
+                    .map(|op| match op {
+                        Value(e) => self.byte(e),
+                        _ => Err("expected a value".to_string()),
+                    })
+                    .collect();
+            }
+            ("DW", ops) => {
+                let mut bytes = vec![];
+                for op in ops {
+                    match op {
+                        Value(e) => {
+                            bytes.extend(&(self.eval_range(e, 0, 0xFFFF)? as u16).to_be_bytes())
+                        }
+                        _ => return Err("expected a value".to_string()),
+                    }
+                }
+                return Ok(bytes);
+            }
+            ("LD", [Operand::I, Long(e)]) => {
+                let mut bytes = encode(LdILong).to_be_bytes().to_vec();
+                bytes.extend(&(self.eval_range(e, 0, 0xFFFF)? as u16).to_be_bytes());
+                return Ok(bytes);
+            }
+            ("CLS", []) => Cls,
+            ("RET", []) => Ret,
+            ("SYS", [Value(a)]) => Sys(self.addr(a)?),
+            ("SCD", [Value(n)]) => ScrollDown(self.nibble(n)?),
+            ("SCU", [Value(n)]) => ScrollUp(self.nibble(n)?),
+            ("SCR", []) => ScrollRight,
+            ("SCL", []) => ScrollLeft,
+            ("EXIT", []) => Exit,
+            ("LOW", []) => Low,
+            ("HIGH", []) => High,
+            ("JP", [Value(a)]) => Jp(self.addr(a)?),
+            ("JP", [Reg(0), Value(a)]) => JpV0(self.addr(a)?),
+            ("CALL", [Value(a)]) => Call(self.addr(a)?),
+            ("SE", [Reg(x), Value(b)]) => SeByte {
+                x: *x,
+                byte: self.byte(b)?,
+            },
+            ("SE", [Reg(x), Reg(y)]) => SeReg { x: *x, y: *y },
+            ("SNE", [Reg(x), Value(b)]) => SneByte {
+                x: *x,
+                byte: self.byte(b)?,
+            },
+            ("SNE", [Reg(x), Reg(y)]) => SneReg { x: *x, y: *y },
+            ("SAVE", [Reg(x), Reg(y)]) => SaveRange { x: *x, y: *y },
+            ("LOAD", [Reg(x), Reg(y)]) => LoadRange { x: *x, y: *y },
+            ("LD", [Reg(x), Value(b)]) => LdByte {
+                x: *x,
+                byte: self.byte(b)?,
+            },
+            ("LD", [Reg(x), Reg(y)]) => Ld { x: *x, y: *y },
+            ("LD", [Operand::I, Value(a)]) => LdI(self.addr(a)?),
+            ("LD", [Reg(x), Operand::Dt]) => LdVxDt(*x),
+            ("LD", [Reg(x), Operand::K]) => LdVxK(*x),
+            ("LD", [Operand::Dt, Reg(x)]) => LdDtVx(*x),
+            ("LD", [Operand::St, Reg(x)]) => LdStVx(*x),
+            ("LD", [Operand::F, Reg(x)]) => LdF(*x),
+            ("LD", [Operand::Hf, Reg(x)]) => LdHf(*x),
+            ("LD", [Operand::B, Reg(x)]) => LdB(*x),
+            ("LD", [Operand::IndirectI, Reg(x)]) => LdIVx(*x),
+            ("LD", [Reg(x), Operand::IndirectI]) => LdVxI(*x),
+            ("LD", [Operand::R, Reg(x)]) => LdRVx(*x),
+            ("LD", [Reg(x), Operand::R]) => LdVxR(*x),
+            ("ADD", [Reg(x), Value(b)]) => AddByte {
+                x: *x,
+                byte: self.byte(b)?,
+            },
+            ("ADD", [Reg(x), Reg(y)]) => Add { x: *x, y: *y },
+            ("ADD", [Operand::I, Reg(x)]) => AddI(*x),
+            ("OR", [Reg(x), Reg(y)]) => Or { x: *x, y: *y },
+            ("AND", [Reg(x), Reg(y)]) => And { x: *x, y: *y },
+            ("XOR", [Reg(x), Reg(y)]) => Xor { x: *x, y: *y },
+            ("SUB", [Reg(x), Reg(y)]) => Sub { x: *x, y: *y },
+            ("SUBN", [Reg(x), Reg(y)]) => Subn { x: *x, y: *y },
+            ("SHR", [Reg(x)]) => Shr { x: *x, y: *x },
+            ("SHR", [Reg(x), Reg(y)]) => Shr { x: *x, y: *y },
+            ("SHL", [Reg(x)]) => Shl { x: *x, y: *x },
+            ("SHL", [Reg(x), Reg(y)]) => Shl { x: *x, y: *y },
+            ("RND", [Reg(x), Value(b)]) => Rnd {
+                x: *x,
+                byte: self.byte(b)?,
+            },
+            ("DRW", [Reg(x), Reg(y), Value(n)]) => Drw {
+                x: *x,
+                y: *y,
+                n: self.nibble(n)?,
+            },
+            ("SKP", [Reg(x)]) => Skp(*x),
+            ("SKNP", [Reg(x)]) => Sknp(*x),
+            ("PLANE", [Value(n)]) => Plane(self.nibble(n)?),
+            ("AUDIO", []) => Audio,
+            ("PITCH", [Reg(x)]) => Pitch(*x),
+            (m, _) if MNEMONICS.contains(&m) => {
+                return Err(format!("invalid operands for {}", m));
+            }
+            _ => return Err(format!("unknown instruction '{}'", statement.mnemonic)),
+        };
+        Ok(encode(instruction).to_be_bytes().to_vec())
+    }
+}
+
+enum DirectiveError {
+    /// An error in an included file, already located.
+    Nested(AsmError),
+    Message(String),
+}
+
+impl From<String> for DirectiveError {
+    fn from(message: String) -> Self {
+        DirectiveError::Message(message)
+    }
+}
+
+/// Splits `"name rest"`, checking that the name is a valid symbol.
+fn split_name(s: &str) -> Result<(&str, &str), DirectiveError> {
+    let mut words = s.splitn(2, char::is_whitespace);
+    let name = words.next().unwrap_or("");
+    let rest = words.next().unwrap_or("").trim();
+    if !is_identifier(name) || rest.is_empty() {
+        return Err(DirectiveError::Message(
+            "expected a name and a value".to_string(),
+        ));
+    }
+    Ok((name, rest))
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Parses `V0` to `VF`.
+fn register(s: &str) -> Option<u8> {
+    let mut chars = s.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('V'), Some(c), None) | (Some('v'), Some(c), None) => c.to_digit(16).map(|d| d as u8),
+        _ => None,
+    }
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_expr(s: &str) -> Result<Expr, String> {
+    let mut expr = vec![];
+    let mut negative = false;
+    let mut rest = s.trim();
+    loop {
+        if let Some(r) = rest.strip_prefix('-') {
+            negative = !negative;
+            rest = r.trim_start();
+            continue;
+        }
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        expr.push((negative, parse_term(rest[..end].trim())?));
+        rest = &rest[end..];
+        negative = match rest.chars().next() {
+            Some('+') => false,
+            Some(_) => true,
+            None => return Ok(expr),
+        };
+        rest = rest[1..].trim_start();
+    }
+}
+
+fn parse_term(s: &str) -> Result<Term, String> {
+    let lower = s.to_lowercase();
+    let number = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16)
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2)
+    } else if s.starts_with(|c: char| c.is_ascii_digit()) {
+        s.parse()
+    } else if is_identifier(s) {
+        return Ok(Term::Symbol(s.to_string()));
+    } else if s.is_empty() {
+        return Err("missing value".to_string());
+    } else {
+        return Err(format!("invalid value '{}'", s));
+    };
+    number
+        .map(Term::Number)
+        .map_err(|_| format!("invalid number '{}'", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decode;
+
+    fn error(source: &str) -> (usize, String) {
+        let e = assemble(source).unwrap_err();
+        (e.line, e.message)
+    }
+
+    #[test]
+    fn instructions() {
+        let rom = assemble(
+            "CLS\n\
+             ld v0, 0x0A\n\
+             DRW V0, V1, 5\n\
+             LD [I], VE\n\
+             SHR V3\n\
+             LD I, LONG 0x1234\n\
+             JP V0, 0x300",
+        )
+        .unwrap();
+        assert_eq!(
+            rom,
+            vec![
+                0x00, 0xE0, 0x60, 0x0A, 0xD0, 0x15, 0xFE, 0x55, 0x83, 0x36, 0xF0, 0x00, 0x12, 0x34,
+                0xB3, 0x00
+            ]
+        );
+    }
+
+    #[test]
+    fn labels() {
+        let rom = assemble(
+            "start:  CALL sub ; forward reference\n\
+             \x20       JP start\n\
+             sub:\n\
+             \x20       RET",
+        )
+        .unwrap();
+        assert_eq!(rom, vec![0x22, 0x04, 0x12, 0x00, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn alias_and_const() {
+        let rom = assemble(
+            ":alias counter V3\n\
+             :const STEP 2\n\
+             :const TWICE STEP + STEP\n\
+             ADD counter, TWICE - 1\n\
+             SE counter, -1",
+        )
+        .unwrap();
+        assert_eq!(rom, vec![0x73, 0x03, 0x33, 0xFF]);
+    }
+
+    #[test]
+    fn data() {
+        let rom = assemble("data: db 1, 0b10, 0xFF\ndw data, 0x1234").unwrap();
+        assert_eq!(rom, vec![1, 2, 0xFF, 0x02, 0x00, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}", std::process::id()));
+        fs::create_dir_all(dir.join("lib")).unwrap();
+        fs::write(dir.join("main.asm"), ":include \"lib/sub.asm\"\nCALL sub\n").unwrap();
+        fs::write(dir.join("lib/sub.asm"), "sub: RET\n:include \"bad.asm\"\n").unwrap();
+        fs::write(dir.join("lib/bad.asm"), "\nNOPE\n").unwrap();
+        let e = assemble_file(dir.join("main.asm")).unwrap_err();
+        assert!(e.file.ends_with("bad.asm"), "{}", e);
+        assert_eq!(e.line, 2);
+        fs::write(dir.join("lib/bad.asm"), "; fixed\n").unwrap();
+        let rom = assemble_file(dir.join("main.asm")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(rom, vec![0x00, 0xEE, 0x22, 0x00]);
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            error("CLS\nFOO V0"),
+            (2, "unknown instruction 'FOO'".to_string())
+        );
+        assert_eq!(
+            error("\n\nJP V1, 0"),
+            (3, "invalid operands for JP".to_string())
+        );
+        assert_eq!(
+            error("JP nowhere"),
+            (1, "unknown symbol 'nowhere'".to_string())
+        );
+        assert_eq!(
+            error("a: CLS\na: CLS"),
+            (2, "'a' is already defined".to_string())
+        );
+        assert_eq!(
+            error("LD V0, 256"),
+            (1, "value 256 out of range -128..255".to_string())
+        );
+        assert_eq!(error("db 1,"), (1, "missing value".to_string()));
+        assert_eq!(
+            error(":alias x 3"),
+            (1, "'3' is not a register".to_string())
+        );
+        assert_eq!(
+            assemble("\nRET\nLD V0").unwrap_err().to_string(),
+            "<input>:3: invalid operands for LD"
+        );
+    }
+
+    #[test]
+    fn assembles_disassembly() {
+        for opcode in 0..=u16::MAX {
+            match decode(opcode) {
+                Ok(Instruction::LdILong) | Err(_) => {}
+                Ok(instruction) => {
+                    let source = instruction.to_string();
+                    assert_eq!(
+                        assemble(&source),
+                        Ok(opcode.to_be_bytes().to_vec()),
+                        "{}",
+                        source
+                    );
+                }
+            }
+        }
+    }
+}
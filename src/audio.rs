@@ -0,0 +1,295 @@
+use crate::{Chip8, Platform};
+use std::fs::File;
+use std::io::{self, BufWriter, Seek, SeekFrom, Write};
+use std::path::Path;
+
+pub const DEFAULT_SAMPLE_RATE: u32 = 44100;
+pub const DEFAULT_FREQUENCY: f32 = 440.0;
+pub const DEFAULT_VOLUME: f32 = 0.25;
+
+/// Generates the sound of the machine: a square wave while the sound timer is
+/// active, or the audio pattern for XO-CHIP programs that set one.
+pub struct Buzzer {
+    sample_rate: u32,
+    frequency: f32,
+    volume: f32,
+    muted: bool,
+    active: bool,
+    pattern: Option<([u8; 16], f64)>,
+    // position within a period of the square wave, or bit of the pattern
+    phase: f64,
+}
+
+impl Buzzer {
+    pub fn new(sample_rate: u32) -> Buzzer {
+        Buzzer {
+            sample_rate,
+            frequency: DEFAULT_FREQUENCY,
+            volume: DEFAULT_VOLUME,
+            muted: false,
+            active: false,
+            pattern: None,
+            phase: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    /// Sets the volume, clamped between 0.0 and 1.0.
+    pub fn set_volume(&mut self, volume: f32) {
+        self.volume = volume.clamp(0.0, 1.0);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn set_muted(&mut self, muted: bool) {
+        self.muted = muted;
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        if !active {
+            self.phase = 0.0;
+        }
+        self.active = active;
+    }
+
+    /// Follows the sound timer and audio pattern of `chip`.
+    pub fn update(&mut self, chip: &Chip8) {
+        self.set_active(chip.sound() > 0);
+        let pattern = Some(*chip.audio_pattern())
+            .filter(|p| chip.platform() == Platform::XoChip && p.iter().any(|b| *b != 0))
+            .map(|p| (p, chip.playback_rate()));
+        if pattern.is_some() != self.pattern.is_some() {
+            self.phase = 0.0;
+        }
+        self.pattern = pattern;
+    }
+
+    /// Fills `buf` with the next samples, between -1.0 and 1.0.
+    pub fn fill(&mut self, buf: &mut [f32]) {
+        for sample in buf.iter_mut() {
+            *sample = self.next_sample();
+        }
+    }
+
+    /// Returns the next `n` samples.
+    pub fn samples(&mut self, n: usize) -> Vec<f32> {
+        let mut buf = vec![0.0; n];
+        self.fill(&mut buf);
+        buf
+    }
+
+    fn next_sample(&mut self) -> f32 {
+        if !self.active {
+            return 0.0;
+        }
+        let high = match self.pattern {
+            Some((bits, rate)) => {
+                let bit = self.phase as usize;
+                self.phase = (self.phase + rate / self.sample_rate as f64) % 128.0;
+                bits[bit / 8] & (0x80 >> (bit % 8)) != 0
+            }
+            None => {
+                let high = self.phase < 0.5;
+                self.phase = (self.phase + self.frequency as f64 / self.sample_rate as f64) % 1.0;
+                high
+            }
+        };
+        match (self.muted, high) {
+            (true, _) => 0.0,
+            (false, true) => self.volume,
+            (false, false) => -self.volume,
+        }
+    }
+}
+
+/// Somewhere to send the samples produced by a `Buzzer`.
+pub trait AudioSink {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()>;
+}
+
+/// Discards all samples, for machines without a sound card.
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn write(&mut self, _samples: &[f32]) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes samples to a mono 16 bit PCM WAV file.
+pub struct WavSink<W: Write + Seek> {
+    writer: Option<W>,
+    data_len: u32,
+}
+
+impl WavSink<BufWriter<File>> {
+    pub fn create<P: AsRef<Path>>(path: P, sample_rate: u32) -> io::Result<Self> {
+        WavSink::new(BufWriter::new(File::create(path)?), sample_rate)
+    }
+}
+
+impl<W: Write + Seek> WavSink<W> {
+    pub fn new(mut writer: W, sample_rate: u32) -> io::Result<Self> {
+        writer.write_all(b"RIFF")?;
+        writer.write_all(&36u32.to_le_bytes())?;
+        writer.write_all(b"WAVEfmt ")?;
+        writer.write_all(&16u32.to_le_bytes())?;
+        // PCM, mono
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&1u16.to_le_bytes())?;
+        writer.write_all(&sample_rate.to_le_bytes())?;
+        writer.write_all(&(sample_rate * 2).to_le_bytes())?;
+        writer.write_all(&2u16.to_le_bytes())?;
+        writer.write_all(&16u16.to_le_bytes())?;
+        writer.write_all(b"data")?;
+        writer.write_all(&0u32.to_le_bytes())?;
+        Ok(WavSink {
+            writer: Some(writer),
+            data_len: 0,
+        })
+    }
+
+    /// Fills in the sizes in the header and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_sizes()?;
+        Ok(self.writer.take().unwrap())
+    }
+
+    fn write_sizes(&mut self) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        writer.seek(SeekFrom::Start(4))?;
+        writer.write_all(&(36 + self.data_len).to_le_bytes())?;
+        writer.seek(SeekFrom::Start(40))?;
+        writer.write_all(&self.data_len.to_le_bytes())?;
+        writer.seek(SeekFrom::End(0))?;
+        writer.flush()
+    }
+}
+
+impl<W: Write + Seek> AudioSink for WavSink<W> {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        let writer = self.writer.as_mut().unwrap();
+        for sample in samples {
+            let value = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        self.data_len += 2 * samples.len() as u32;
+        Ok(())
+    }
+}
+
+impl<W: Write + Seek> Drop for WavSink<W> {
+    fn drop(&mut self) {
+        if self.writer.is_some() {
+            let _ = self.write_sizes();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn silent_when_inactive() {
+        let mut buzzer = Buzzer::new(8);
+        assert_eq!(buzzer.samples(4), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn square_wave() {
+        let mut buzzer = Buzzer::new(8);
+        buzzer.set_frequency(2.0);
+        buzzer.set_volume(0.5);
+        buzzer.set_active(true);
+        assert_eq!(buzzer.samples(6), vec![0.5, 0.5, -0.5, -0.5, 0.5, 0.5]);
+    }
+
+    #[test]
+    fn mute() {
+        let mut buzzer = Buzzer::new(8);
+        buzzer.set_active(true);
+        buzzer.toggle_mute();
+        assert!(buzzer.is_muted());
+        assert_eq!(buzzer.samples(4), vec![0.0; 4]);
+    }
+
+    #[test]
+    fn volume_is_clamped() {
+        let mut buzzer = Buzzer::new(8);
+        buzzer.set_volume(2.0);
+        assert_eq!(buzzer.volume(), 1.0);
+        buzzer.set_volume(-1.0);
+        assert_eq!(buzzer.volume(), 0.0);
+    }
+
+    #[test]
+    fn follows_sound_timer() {
+        let mut chip = Chip8::new(vec![0x60, 0x02, 0xF0, 0x18]);
+        let mut buzzer = Buzzer::new(8);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        buzzer.update(&chip);
+        assert!(buzzer.is_active());
+        chip.tick_timers();
+        chip.tick_timers();
+        buzzer.update(&chip);
+        assert!(!buzzer.is_active());
+    }
+
+    #[test]
+    fn xo_pattern() {
+        let prog = vec![0xA2, 0x0A, 0xF0, 0x02, 0x60, 0x01, 0xF0, 0x18, 0x00, 0x00];
+        let mut rom = prog;
+        rom.extend_from_slice(&[0xAA; 16]);
+        let mut chip = Chip8::with_platform(Platform::XoChip, &rom).unwrap();
+        for _ in 0..4 {
+            chip.step().unwrap();
+        }
+        // 4000 bits per second
+        let mut buzzer = Buzzer::new(4000);
+        buzzer.set_volume(1.0);
+        buzzer.update(&chip);
+        assert_eq!(buzzer.samples(4), vec![1.0, -1.0, 1.0, -1.0]);
+    }
+
+    #[test]
+    fn wav_sink() {
+        let mut sink = WavSink::new(Cursor::new(vec![]), 8000).unwrap();
+        sink.write(&[1.0, -1.0, 0.0]).unwrap();
+        let wav = sink.finish().unwrap().into_inner();
+        assert_eq!(wav.len(), 44 + 6);
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(wav[4..8], 42u32.to_le_bytes());
+        assert_eq!(wav[24..28], 8000u32.to_le_bytes());
+        assert_eq!(wav[40..44], 6u32.to_le_bytes());
+        assert_eq!(wav[44..46], i16::MAX.to_le_bytes());
+        assert_eq!(wav[46..48], (-i16::MAX).to_le_bytes());
+    }
+}
@@ -0,0 +1,39 @@
+use chip8::asm;
+use clap::{App, Arg};
+use std::fs;
+use std::path::Path;
+use std::process;
+
+fn main() {
+    let matches = App::new("Chip-8 assembler")
+        .version("0.1")
+        .about("Assembles a Chip-8 program into a ROM.")
+        .arg(
+            Arg::with_name("source")
+                .value_name("PATH")
+                .help("The program to assemble")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("output")
+                .short("o")
+                .long("output")
+                .value_name("PATH")
+                .help("Where to write the ROM, defaults to the source with a .ch8 extension")
+                .takes_value(true),
+        )
+        .get_matches();
+    let source = matches.value_of("source").unwrap();
+    let output = match matches.value_of("output") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => Path::new(source).with_extension("ch8"),
+    };
+    let rom = asm::assemble_file(source).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    if let Err(e) = fs::write(&output, rom) {
+        eprintln!("Can't write '{}': {}", output.display(), e);
+        process::exit(1);
+    }
+}
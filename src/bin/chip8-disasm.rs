@@ -0,0 +1,49 @@
+use chip8::disasm::{self, Syntax};
+use clap::{App, Arg};
+use std::fs;
+use std::process;
+
+fn main() {
+    let matches = App::new("Chip-8 disassembler")
+        .version("0.1")
+        .about("Prints a listing of a Chip-8 ROM.")
+        .arg(
+            Arg::with_name("rom")
+                .value_name("PATH")
+                .help("The ROM to disassemble")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("syntax")
+                .long("syntax")
+                .value_name("SYNTAX")
+                .help("Assembly syntax of the listing")
+                .possible_values(&["cowgod", "octo"])
+                .default_value("cowgod")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("origin")
+                .long("origin")
+                .value_name("ADDR")
+                .help("Address the ROM is loaded at, in hexadecimal")
+                .default_value("200")
+                .takes_value(true),
+        )
+        .get_matches();
+    let path = matches.value_of("rom").unwrap();
+    let rom = fs::read(path).unwrap_or_else(|e| {
+        eprintln!("Can't read '{}': {}", path, e);
+        process::exit(1);
+    });
+    let syntax = match matches.value_of("syntax").unwrap() {
+        "octo" => Syntax::Octo,
+        _ => Syntax::Cowgod,
+    };
+    let origin = matches.value_of("origin").unwrap();
+    let origin = u16::from_str_radix(origin.trim_start_matches("0x"), 16).unwrap_or_else(|_| {
+        eprintln!("Invalid origin: '{}'", origin);
+        process::exit(1);
+    });
+    print!("{}", disasm::disassemble(&rom, origin, syntax));
+}
@@ -0,0 +1,225 @@
+use chip8::export::{self, Image};
+use chip8::headless::{Condition, KeyScript, Runner, Stop};
+use chip8::movie::Movie;
+use chip8::{Chip8, MemoryPolicy, Platform, Quirks};
+use clap::{App, Arg};
+use std::fs;
+use std::process;
+
+// exit codes, 1 is for usage and I/O errors
+const EXIT_ERROR: i32 = 2;
+const EXIT_FRAME_LIMIT: i32 = 3;
+
+/// Parses `ADDR=VALUE`, both in hex.
+fn parse_memory_condition(condition: &str) -> Option<Condition> {
+    let mut parts = condition.splitn(2, '=');
+    let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let value = u8::from_str_radix(parts.next()?, 16).ok()?;
+    Some(Condition::Memory { addr, value })
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let matches = App::new("Chip-8 headless runner")
+        .version("0.1")
+        .about("Runs a Chip-8 ROM without a display and prints the final state.")
+        .after_help(
+            "Exits with 0 when a stop condition is met, the program exits or, \
+             without conditions, the frame limit is reached; 2 when emulation \
+             fails; 3 when the frame limit is reached before any condition.",
+        )
+        .arg(
+            Arg::with_name("rom")
+                .value_name("PATH")
+                .help("The ROM to run")
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("Selects the machine the ROM was written for")
+                .possible_values(&["chip8", "schip", "xochip"])
+                .default_value("chip8")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quirks")
+                .long("quirks")
+                .value_name("PROFILE")
+                .help("Emulates the behaviour of a specific interpreter")
+                .possible_values(&["vip", "chip48", "schip", "xochip"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cpu-hz")
+                .long("cpu-hz")
+                .value_name("HZ")
+                .help("Number of instructions executed per second")
+                .default_value("700")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seeds the random number generator, for reproducible runs")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wrap-memory")
+                .long("wrap-memory")
+                .help("Wraps memory accesses past the end of memory instead of stopping"),
+        )
+        .arg(
+            Arg::with_name("frames")
+                .long("frames")
+                .value_name("N")
+                .help("Stops after N frames of 1/60 s")
+                .default_value("600")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("until-loop")
+                .long("until-loop")
+                .help("Stops when the program jumps to itself"),
+        )
+        .arg(
+            Arg::with_name("until-memory")
+                .long("until-memory")
+                .value_name("ADDR=VALUE")
+                .help("Stops when the byte at ADDR is VALUE, both in hex")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("keys")
+                .long("keys")
+                .value_name("SCRIPT")
+                .help("Key events such as '60:5 90:+A 95:-A', see the README")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("PATH")
+                .help("Presses the keys of a movie file, with the settings it was recorded with")
+                .conflicts_with("keys")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("png")
+                .long("png")
+                .value_name("PATH")
+                .help("Also writes the final screen to a PNG file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .value_name("N")
+                .help("Size of a pixel in the PNG file")
+                .default_value("1")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("COLOURS")
+                .help("The four colours of the PNG file, as in '000000,FFFFFF,AAAAAA,555555'")
+                .takes_value(true),
+        )
+        .get_matches();
+    let rom = matches.value_of("rom").unwrap();
+    let program =
+        fs::read(rom).unwrap_or_else(|e| fail(format!("Can't open file '{}': {}", rom, e)));
+    let replay = matches.value_of("replay").map(|path| {
+        let text = fs::read_to_string(path)
+            .unwrap_or_else(|e| fail(format!("Can't open file '{}': {}", path, e)));
+        Movie::parse(&text).unwrap_or_else(|e| fail(format!("Invalid movie '{}': {}", path, e)))
+    });
+    let mut chip = match &replay {
+        Some(movie) => movie
+            .start(&program)
+            .unwrap_or_else(|e| fail(format!("Can't replay the movie: {}", e))),
+        None => {
+            let platform = Platform::from_name(matches.value_of("platform").unwrap()).unwrap();
+            let mut chip = Chip8::with_platform(platform, &program)
+                .unwrap_or_else(|e| fail(format!("Can't load '{}': {}", rom, e)));
+            if let Some(profile) = matches.value_of("quirks") {
+                chip.set_quirks(Quirks::preset(profile).unwrap());
+            }
+            if matches.is_present("wrap-memory") {
+                chip.set_memory_policy(MemoryPolicy::Wrap);
+            }
+            if let Some(seed) = matches.value_of("seed") {
+                chip.set_seed(
+                    seed.parse()
+                        .unwrap_or_else(|_| fail("Invalid seed".to_string())),
+                );
+            }
+            chip
+        }
+    };
+    let cycles = match &replay {
+        Some(movie) => movie.cycles_per_frame,
+        None => chip8::cycles_per_frame(
+            matches
+                .value_of("cpu-hz")
+                .unwrap()
+                .parse()
+                .unwrap_or_else(|_| fail("Invalid CPU speed".to_string())),
+        ),
+    };
+    let frames: u64 = matches
+        .value_of("frames")
+        .unwrap()
+        .parse()
+        .unwrap_or_else(|_| fail("Invalid number of frames".to_string()));
+    let mut runner = Runner::new(cycles, frames);
+    if matches.is_present("until-loop") {
+        runner.add_condition(Condition::PcLoop);
+    }
+    for condition in matches.values_of("until-memory").into_iter().flatten() {
+        runner.add_condition(
+            parse_memory_condition(condition)
+                .unwrap_or_else(|| fail(format!("Invalid memory condition '{}'", condition))),
+        );
+    }
+    if let Some(script) = matches.value_of("keys") {
+        runner.set_keys(KeyScript::parse(script).unwrap_or_else(|e| fail(e.to_string())));
+    }
+    if let Some(movie) = replay {
+        runner.set_keys(KeyScript::from_events(movie.events));
+    }
+
+    let report = runner.run(&mut chip);
+    println!("Stopped after {} frames: {}", report.frames, report.stop);
+    print!("{}", chip.registers());
+    print!("{}", export::to_text(chip.screen(), &export::ASCII_CHARS));
+    if let Some(path) = matches.value_of("png") {
+        let scale: usize = match matches.value_of("scale").unwrap().parse() {
+            Ok(scale) if scale > 0 => scale,
+            _ => fail("Invalid scale".to_string()),
+        };
+        let palette = match matches.value_of("palette") {
+            Some(palette) => export::parse_palette(palette)
+                .unwrap_or_else(|| fail(format!("Invalid palette '{}'", palette))),
+            None => export::DEFAULT_PALETTE,
+        };
+        if let Err(e) = Image::render(chip.screen(), scale, &palette).save_png(path) {
+            fail(format!("Can't write '{}': {}", path, e));
+        }
+    }
+    let has_conditions = matches.is_present("until-loop") || matches.is_present("until-memory");
+    process::exit(match report.stop {
+        Stop::Error(_) => EXIT_ERROR,
+        Stop::FrameLimit if has_conditions => EXIT_FRAME_LIMIT,
+        _ => 0,
+    });
+}
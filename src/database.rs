@@ -0,0 +1,338 @@
+//! Per-ROM settings from a database in the format of the community
+//! chip-8-database, whose `programs.json` lists programs with their ROMs keyed
+//! by SHA-1 hash:
+//!
+//! ```json
+//! [{
+//!     "title": "Brix",
+//!     "roms": {
+//!         "<sha1 hex>": {
+//!             "platforms": ["originalChip8", "modernChip8"],
+//!             "quirkyPlatforms": { "originalChip8": { "vblank": false } },
+//!             "tickrate": 15,
+//!             "keys": { "left": 4, "right": 6 },
+//!             "colors": { "pixels": ["#000000", "#ffffff"] }
+//!         }
+//!     }
+//! }]
+//! ```
+//!
+//! The first platform of a ROM that this crate emulates is used, with the
+//! quirks the database gives it. Other fields are ignored.
+//!
+//! A database is shipped with the emulator in `data/programs.json`, see
+//! `Database::bundled`.
+
+use crate::export::{self, Palette};
+use crate::{Chip8, Platform, Quirks};
+use serde_json::{Map, Value};
+use sha1::Sha1;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Errors raised when a database can't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatabaseError {
+    /// The database isn't valid JSON.
+    Json(String),
+    /// A program or ROM entry is invalid.
+    Invalid(String),
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DatabaseError::Json(message) | DatabaseError::Invalid(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+
+impl Error for DatabaseError {}
+
+/// What the database knows about a ROM.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RomInfo {
+    /// The title of the program the ROM belongs to.
+    pub title: String,
+    pub platform: Option<Platform>,
+    /// The quirks of `platform`.
+    pub quirks: Option<Quirks>,
+    /// Instructions per frame.
+    pub tickrate: Option<usize>,
+    /// The keys the program uses, by the database's names such as `up`, `left`
+    /// or `a`.
+    pub keys: Vec<(String, u8)>,
+    /// The colours of the screen, the missing ones are the defaults.
+    pub palette: Option<Palette>,
+}
+
+/// The database shipped with the emulator.
+const BUNDLED: &str = include_str!("../data/programs.json");
+
+/// ROMs by SHA-1 hash.
+#[derive(Debug, Clone, Default)]
+pub struct Database {
+    roms: HashMap<[u8; 20], RomInfo>,
+}
+
+impl Database {
+    pub fn new() -> Database {
+        Database::default()
+    }
+
+    /// The database shipped with the emulator, which user databases extend.
+    pub fn bundled() -> Database {
+        Database::parse(BUNDLED).expect("the bundled database is invalid")
+    }
+
+    pub fn parse(json: &str) -> Result<Database, DatabaseError> {
+        let programs: Value =
+            serde_json::from_str(json).map_err(|e| DatabaseError::Json(e.to_string()))?;
+        let programs = programs
+            .as_array()
+            .ok_or_else(|| DatabaseError::Invalid("expected a list of programs".to_string()))?;
+        let mut database = Database::new();
+        for (i, program) in programs.iter().enumerate() {
+            let title = program
+                .get("title")
+                .and_then(Value::as_str)
+                .map_or_else(|| format!("program {}", i), str::to_string);
+            let roms = program
+                .get("roms")
+                .and_then(Value::as_object)
+                .ok_or_else(|| DatabaseError::Invalid(format!("'{}' has no ROMs", title)))?;
+            for (hash, rom) in roms {
+                let invalid = |message: &str| {
+                    DatabaseError::Invalid(format!("ROM {} of '{}': {}", hash, title, message))
+                };
+                let key = Chip8::parse_rom_hash(hash).ok_or_else(|| invalid("invalid hash"))?;
+                let rom = rom
+                    .as_object()
+                    .ok_or_else(|| invalid("expected an object"))?;
+                let info = parse_rom(&title, rom).map_err(|e| invalid(&e))?;
+                database.roms.insert(key, info);
+            }
+        }
+        Ok(database)
+    }
+
+    /// Adds the ROMs of `other`, replacing the ones already there.
+    pub fn extend(&mut self, other: Database) {
+        self.roms.extend(other.roms);
+    }
+
+    pub fn len(&self) -> usize {
+        self.roms.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.roms.is_empty()
+    }
+
+    /// Looks up a ROM by its contents.
+    pub fn lookup(&self, rom: &[u8]) -> Option<&RomInfo> {
+        self.roms.get(&Sha1::from(rom).digest().bytes())
+    }
+}
+
+/// The platforms of the database this crate emulates, with their quirks.
+fn platform(id: &str) -> Option<(Platform, Quirks)> {
+    match id {
+        "originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::cosmac_vip())),
+        "modernChip8" => Some((
+            Platform::Chip8,
+            Quirks {
+                shift: false,
+                load_store: true,
+                jump: false,
+                clipping: true,
+                vf_reset: false,
+                display_wait: false,
+            },
+        )),
+        "chip48" => Some((Platform::Chip8, Quirks::chip48())),
+        "superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::super_chip())),
+        "xochip" => Some((Platform::XoChip, Quirks::xo_chip())),
+        _ => None,
+    }
+}
+
+/// Applies quirks named as in the database. `memoryIncrementByX` has no
+/// equivalent and is ignored.
+fn apply_quirks(quirks: &mut Quirks, overrides: &Map<String, Value>) -> Result<(), String> {
+    for (name, value) in overrides {
+        let value = value
+            .as_bool()
+            .ok_or_else(|| format!("quirk '{}' isn't a boolean", name))?;
+        match name.as_str() {
+            "shift" => quirks.shift = value,
+            "memoryLeaveIUnchanged" => quirks.load_store = !value,
+            "wrap" => quirks.clipping = !value,
+            "jump" => quirks.jump = value,
+            "vblank" => quirks.display_wait = value,
+            "logic" => quirks.vf_reset = value,
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+fn parse_rom(title: &str, rom: &Map<String, Value>) -> Result<RomInfo, String> {
+    let mut info = RomInfo {
+        title: title.to_string(),
+        platform: None,
+        quirks: None,
+        tickrate: None,
+        keys: vec![],
+        palette: None,
+    };
+    if let Some(platforms) = rom.get("platforms") {
+        let platforms = platforms
+            .as_array()
+            .ok_or_else(|| "platforms isn't a list".to_string())?;
+        let found = platforms
+            .iter()
+            .filter_map(Value::as_str)
+            .find_map(|id| Some((id, platform(id)?)));
+        if let Some((id, (platform, mut quirks))) = found {
+            let overrides = rom
+                .get("quirkyPlatforms")
+                .and_then(|platforms| platforms.get(id))
+                .and_then(Value::as_object);
+            if let Some(overrides) = overrides {
+                apply_quirks(&mut quirks, overrides)?;
+            }
+            info.platform = Some(platform);
+            info.quirks = Some(quirks);
+        }
+    }
+    if let Some(tickrate) = rom.get("tickrate") {
+        match tickrate.as_u64() {
+            Some(tickrate) if tickrate > 0 => info.tickrate = Some(tickrate as usize),
+            _ => return Err(format!("invalid tickrate {}", tickrate)),
+        }
+    }
+    if let Some(keys) = rom.get("keys") {
+        let keys = keys
+            .as_object()
+            .ok_or_else(|| "keys isn't an object".to_string())?;
+        for (name, key) in keys {
+            match key.as_u64() {
+                Some(key) if key < 16 => info.keys.push((name.clone(), key as u8)),
+                _ => return Err(format!("invalid key {} for '{}'", key, name)),
+            }
+        }
+    }
+    if let Some(pixels) = rom.get("colors").and_then(|colors| colors.get("pixels")) {
+        let pixels = pixels
+            .as_array()
+            .ok_or_else(|| "pixel colours aren't a list".to_string())?;
+        let mut palette = export::DEFAULT_PALETTE;
+        for (colour, pixel) in palette.iter_mut().zip(pixels.iter()) {
+            *colour = pixel
+                .as_str()
+                .and_then(export::parse_colour)
+                .ok_or_else(|| format!("invalid colour {}", pixel))?;
+        }
+        info.palette = Some(palette);
+    }
+    Ok(info)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash(rom: &[u8]) -> String {
+        Sha1::from(rom).digest().to_string()
+    }
+
+    #[test]
+    fn lookup() {
+        let json = format!(
+            r##"[
+                {{ "title": "Empty", "roms": {{}} }},
+                {{
+                    "title": "Game",
+                    "origin": {{ "type": "gamejam" }},
+                    "roms": {{
+                        "{}": {{
+                            "file": "game.ch8",
+                            "platforms": ["megachip8", "superchip", "xochip"],
+                            "quirkyPlatforms": {{ "superchip": {{ "shift": false, "wrap": true }} }},
+                            "tickrate": 30,
+                            "keys": {{ "up": 5, "a": 6 }},
+                            "colors": {{ "pixels": ["#102030", "#ffffff"], "buzzer": "#990000" }}
+                        }},
+                        "{}": {{ "platforms": ["chip8x"] }}
+                    }}
+                }}
+            ]"##,
+            hash(&[0x00, 0xE0]),
+            hash(&[0x12, 0x00])
+        );
+        let database = Database::parse(&json).unwrap();
+        assert_eq!(database.len(), 2);
+        let info = database.lookup(&[0x00, 0xE0]).unwrap();
+        assert_eq!(info.title, "Game");
+        assert_eq!(info.platform, Some(Platform::SuperChip));
+        let quirks = Quirks {
+            shift: false,
+            clipping: false,
+            ..Quirks::super_chip()
+        };
+        assert_eq!(info.quirks, Some(quirks));
+        assert_eq!(info.tickrate, Some(30));
+        assert_eq!(info.keys, [("a".to_string(), 6), ("up".to_string(), 5)]);
+        let mut palette = export::DEFAULT_PALETTE;
+        palette[0] = [0x10, 0x20, 0x30];
+        assert_eq!(info.palette, Some(palette));
+
+        // a platform this crate doesn't emulate
+        let info = database.lookup(&[0x12, 0x00]).unwrap();
+        assert_eq!(
+            (info.platform, info.quirks, info.tickrate),
+            (None, None, None)
+        );
+        assert!(database.lookup(&[0x00, 0xEE]).is_none());
+    }
+
+    #[test]
+    fn bundled() {
+        Database::bundled();
+    }
+
+    #[test]
+    fn errors() {
+        let invalid = |rom: &str| {
+            let json = format!(
+                r#"[{{ "title": "T", "roms": {{ "{}": {} }} }}]"#,
+                hash(&[]),
+                rom
+            );
+            match Database::parse(&json) {
+                Err(DatabaseError::Invalid(message)) => message,
+                other => panic!("{:?}", other),
+            }
+        };
+        assert!(invalid(r#"{ "tickrate": "fast" }"#).ends_with("invalid tickrate \"fast\""));
+        assert!(invalid(r#"{ "keys": { "up": 16 } }"#).contains("'T'"));
+        assert!(invalid(r#"{ "colors": { "pixels": ["red"] } }"#).contains("colour"));
+        assert!(invalid(
+            r#"{ "platforms": ["xochip"], "quirkyPlatforms": { "xochip": { "shift": 1 } } }"#
+        )
+        .contains("shift"));
+        assert!(matches!(
+            Database::parse(r#"[{ "roms": { "beef": {} } }]"#),
+            Err(DatabaseError::Invalid(_))
+        ));
+        assert!(matches!(
+            Database::parse("{}"),
+            Err(DatabaseError::Invalid(_))
+        ));
+        assert!(matches!(Database::parse("["), Err(DatabaseError::Json(_))));
+    }
+}
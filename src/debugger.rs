@@ -0,0 +1,601 @@
+//! An interactive debugger: breakpoints, watchpoints and single stepping,
+//! controlled with text commands.
+//!
+//! The frontend feeds the lines typed by the user to `Debugger::command`, and
+//! runs the machine through `Debugger::run_frame` instead of
+//! `Chip8::run_frame` so that execution stops when a breakpoint is hit.
+
+use crate::disasm::{self, Syntax};
+use crate::{Chip8, Chip8Error, StepOutcome};
+use std::fmt;
+use std::fmt::Write;
+
+const HELP: &str = "\
+step [N]            execute N instructions, 1 by default
+continue            resume execution
+pause               stop execution
+break ADDR          stop before executing the instruction at ADDR
+breakop PATTERN     stop before executing an opcode matching PATTERN, e.g. D... or 00E0
+watch ADDR [END]    stop when memory between ADDR and END changes
+watch VX | I        stop when a register changes
+list                list breakpoints and watchpoints
+delete N            delete breakpoint or watchpoint N
+regs                show V0-VF, I, PC, SP and the timers
+set REG VALUE       set VX, I, PC, DT or ST
+poke ADDR BYTE...   write bytes to memory
+stack               show the call stack
+mem ADDR [LEN]      show LEN bytes of memory, 64 by default
+dis [ADDR] [N]      disassemble N instructions from ADDR, the PC by default
+help                show this message
+Addresses are hexadecimal. An empty line repeats the last command.
+";
+
+/// A condition that pauses execution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Before the instruction at this address is executed.
+    Address(u16),
+    /// Before an opcode equal to `value` in the bits set in `mask` is executed.
+    Opcode { mask: u16, value: u16 },
+    /// After memory between `start` and `end` inclusive changes.
+    Memory { start: u16, end: u16 },
+    /// After a V register changes.
+    Register(u8),
+    /// After I changes.
+    I,
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Breakpoint::Address(addr) => write!(f, "break at {:04X}", addr),
+            Breakpoint::Opcode { mask, value } => {
+                write!(f, "break on opcode ")?;
+                for shift in [12, 8, 4, 0].iter() {
+                    if (mask >> shift) & 0xF == 0 {
+                        write!(f, ".")?;
+                    } else {
+                        write!(f, "{:X}", (value >> shift) & 0xF)?;
+                    }
+                }
+                Ok(())
+            }
+            Breakpoint::Memory { start, end } => write!(f, "watch {:04X}-{:04X}", start, end),
+            Breakpoint::Register(x) => write!(f, "watch V{:X}", x),
+            Breakpoint::I => write!(f, "watch I"),
+        }
+    }
+}
+
+impl Breakpoint {
+    fn is_watchpoint(&self) -> bool {
+        !matches!(self, Breakpoint::Address(_) | Breakpoint::Opcode { .. })
+    }
+
+    /// The value watched by a watchpoint.
+    fn watched(&self, chip: &Chip8) -> Vec<u8> {
+        match *self {
+            Breakpoint::Memory { start, end } => {
+                let mem = chip.memory();
+                let end = (end as usize).min(mem.len() - 1);
+                mem.get(start as usize..=end).unwrap_or(&[]).to_vec()
+            }
+            Breakpoint::Register(x) => vec![chip.v()[x as usize]],
+            Breakpoint::I => chip.i().to_be_bytes().to_vec(),
+            _ => vec![],
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Debugger {
+    breakpoints: Vec<Breakpoint>,
+    paused: bool,
+    // the next instruction runs even if a breakpoint matches it, so that
+    // execution can resume from a breakpoint
+    resuming: bool,
+    last_command: String,
+    message: Option<String>,
+}
+
+impl Debugger {
+    pub fn new() -> Debugger {
+        Debugger::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+        self.resuming = true;
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) {
+        self.breakpoints.push(breakpoint);
+    }
+
+    /// Returns why execution last stopped, once.
+    pub fn take_message(&mut self) -> Option<String> {
+        self.message.take()
+    }
+
+    /// Runs a frame like `Chip8::run_frame`, unless paused. Stops in the middle
+    /// of the frame when a breakpoint is hit, in which case the timers aren't
+    /// ticked.
+    pub fn run_frame(
+        &mut self,
+        chip: &mut Chip8,
+        cycles: usize,
+    ) -> Result<StepOutcome, Chip8Error> {
+        if self.paused {
+            return Ok(StepOutcome::Executed);
+        }
+        let mut outcome = StepOutcome::Executed;
+        for _ in 0..cycles {
+            outcome = self.step(chip)?;
+            if self.paused {
+                return Ok(outcome);
+            }
+            match outcome {
+                StepOutcome::Executed => {}
+                StepOutcome::Exited => return Ok(outcome),
+                StepOutcome::WaitingForKey | StepOutcome::WaitingForVBlank => break,
+            }
+        }
+        chip.tick_timers();
+        Ok(outcome)
+    }
+
+    /// Executes one instruction, checking the breakpoints.
+    fn step(&mut self, chip: &mut Chip8) -> Result<StepOutcome, Chip8Error> {
+        if !self.resuming {
+            if let Some(message) = self.check_breakpoints(chip) {
+                self.stop(message);
+                return Ok(StepOutcome::Executed);
+            }
+        }
+        // only watchpoints need a snapshot, and without any nothing is copied
+        let before: Vec<(usize, Vec<u8>)> = self
+            .breakpoints
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.is_watchpoint())
+            .map(|(i, b)| (i, b.watched(chip)))
+            .collect();
+        let pc = chip.pc();
+        let result = chip.step();
+        // waiting doesn't execute anything, the breakpoint still has to be
+        // skipped the next time
+        if let Ok(StepOutcome::Executed) | Err(_) = result {
+            self.resuming = false;
+        }
+        let hit = before.into_iter().find_map(|(i, before)| {
+            let breakpoint = &self.breakpoints[i];
+            let after = breakpoint.watched(chip);
+            if after == before {
+                return None;
+            }
+            Some(format!(
+                "{} hit at {:04X}: {} -> {}",
+                breakpoint,
+                pc,
+                hex(&before),
+                hex(&after)
+            ))
+        });
+        if let Some(message) = hit {
+            self.stop(message);
+        }
+        result
+    }
+
+    fn check_breakpoints(&self, chip: &Chip8) -> Option<String> {
+        let pc = chip.pc();
+        let opcode = opcode_at(chip, pc)?;
+        self.breakpoints
+            .iter()
+            .find(|b| match **b {
+                Breakpoint::Address(addr) => addr == pc,
+                Breakpoint::Opcode { mask, value } => opcode & mask == value,
+                _ => false,
+            })
+            .map(|b| format!("{} hit at {:04X}", b, pc))
+    }
+
+    fn stop(&mut self, message: String) {
+        self.paused = true;
+        self.message = Some(message);
+    }
+
+    /// Runs a command and returns its output.
+    pub fn command(&mut self, line: &str, chip: &mut Chip8) -> String {
+        let line = if line.trim().is_empty() {
+            self.last_command.clone()
+        } else {
+            line.trim().to_string()
+        };
+        self.last_command = line.clone();
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let result = match words.as_slice() {
+            [] => Ok(String::new()),
+            ["s"] | ["step"] => self.step_command(chip, 1),
+            ["s", n] | ["step", n] => match n.parse() {
+                Ok(n) => self.step_command(chip, n),
+                Err(_) => Err(format!("invalid count '{}'", n)),
+            },
+            ["c"] | ["continue"] => {
+                self.resume();
+                Ok(String::new())
+            }
+            ["pause"] => {
+                self.pause();
+                Ok(self.location(chip))
+            }
+            ["b", addr] | ["break", addr] => {
+                parse_addr(addr).map(|addr| self.add(Breakpoint::Address(addr)))
+            }
+            ["breakop", pattern] => parse_pattern(pattern)
+                .map(|(mask, value)| self.add(Breakpoint::Opcode { mask, value })),
+            ["w", what] | ["watch", what] => match what.to_uppercase().as_str() {
+                "I" => Ok(self.add(Breakpoint::I)),
+                reg if reg.len() == 2 && reg.starts_with('V') => {
+                    match u8::from_str_radix(&reg[1..], 16) {
+                        Ok(x) => Ok(self.add(Breakpoint::Register(x))),
+                        Err(_) => Err(format!("invalid register '{}'", what)),
+                    }
+                }
+                _ => parse_addr(what).map(|addr| {
+                    self.add(Breakpoint::Memory {
+                        start: addr,
+                        end: addr,
+                    })
+                }),
+            },
+            ["w", start, end] | ["watch", start, end] => parse_addr(start)
+                .and_then(|start| Ok((start, parse_addr(end)?)))
+                .and_then(|(start, end)| {
+                    if start > end {
+                        return Err("the range is empty".to_string());
+                    }
+                    Ok(self.add(Breakpoint::Memory { start, end }))
+                }),
+            ["l"] | ["list"] => Ok(self.list()),
+            ["d", n] | ["delete", n] => match n.parse::<usize>() {
+                Ok(n) if n >= 1 && n <= self.breakpoints.len() => {
+                    let breakpoint = self.breakpoints.remove(n - 1);
+                    Ok(format!("deleted {}\n", breakpoint))
+                }
+                _ => Err(format!("no breakpoint {}", n)),
+            },
+            ["set", reg, value] => set(chip, reg, value),
+            ["poke", addr, bytes @ ..] if !bytes.is_empty() => parse_addr(addr).and_then(|addr| {
+                let bytes = bytes
+                    .iter()
+                    .map(|b| parse_byte(b))
+                    .collect::<Result<Vec<u8>, _>>()?;
+                chip.debug().poke(addr, &bytes).map_err(|e| e.to_string())?;
+                Ok(memory(chip, addr, bytes.len()))
+            }),
+            ["r"] | ["regs"] => Ok(chip.registers().to_string()),
+            ["stack"] => Ok(stack(chip)),
+            ["m", addr] | ["mem", addr] => parse_addr(addr).map(|addr| memory(chip, addr, 64)),
+            ["m", addr, len] | ["mem", addr, len] => {
+                parse_addr(addr).and_then(|addr| match len.parse() {
+                    Ok(len) => Ok(memory(chip, addr, len)),
+                    Err(_) => Err(format!("invalid length '{}'", len)),
+                })
+            }
+            ["dis"] => Ok(disassemble(chip, chip.pc(), 8)),
+            ["dis", addr] => parse_addr(addr).map(|addr| disassemble(chip, addr, 8)),
+            ["dis", addr, n] => parse_addr(addr).and_then(|addr| match n.parse() {
+                Ok(n) => Ok(disassemble(chip, addr, n)),
+                Err(_) => Err(format!("invalid count '{}'", n)),
+            }),
+            ["h"] | ["help"] => Ok(HELP.to_string()),
+            _ => Err(format!("unknown command '{}', try 'help'", line)),
+        };
+        match result {
+            Ok(output) => output,
+            Err(e) => format!("error: {}\n", e),
+        }
+    }
+
+    fn add(&mut self, breakpoint: Breakpoint) -> String {
+        self.breakpoints.push(breakpoint);
+        format!("{}: {}\n", self.breakpoints.len(), breakpoint)
+    }
+
+    fn list(&self) -> String {
+        if self.breakpoints.is_empty() {
+            return "no breakpoints\n".to_string();
+        }
+        let mut out = String::new();
+        for (n, breakpoint) in self.breakpoints.iter().enumerate() {
+            writeln!(out, "{}: {}", n + 1, breakpoint).unwrap();
+        }
+        out
+    }
+
+    /// Steps `n` instructions while paused. Timers don't run.
+    fn step_command(&mut self, chip: &mut Chip8, n: usize) -> Result<String, String> {
+        self.paused = true;
+        self.resuming = true;
+        let mut out = String::new();
+        for _ in 0..n {
+            match self.step(chip) {
+                Ok(StepOutcome::Executed) => {}
+                Ok(StepOutcome::WaitingForKey) => {
+                    out.push_str("waiting for a key press\n");
+                    break;
+                }
+                Ok(StepOutcome::WaitingForVBlank) => {
+                    chip.tick_timers();
+                }
+                Ok(StepOutcome::Exited) => {
+                    out.push_str("the program exited\n");
+                    break;
+                }
+                Err(e) => return Err(e.to_string()),
+            }
+            if let Some(message) = self.message.take() {
+                writeln!(out, "{}", message).unwrap();
+                break;
+            }
+        }
+        out.push_str(&self.location(chip));
+        Ok(out)
+    }
+
+    /// The next instruction.
+    fn location(&self, chip: &Chip8) -> String {
+        disassemble(chip, chip.pc(), 1)
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02X}", b)).collect()
+}
+
+fn opcode_at(chip: &Chip8, addr: u16) -> Option<u16> {
+    let mem = chip.memory();
+    let hi = *mem.get(addr as usize)?;
+    let lo = *mem.get(addr as usize + 1)?;
+    Some(((hi as u16) << 8) | lo as u16)
+}
+
+fn parse_addr(s: &str) -> Result<u16, String> {
+    let digits = s.trim_start_matches("0x");
+    u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", s))
+}
+
+fn parse_byte(s: &str) -> Result<u8, String> {
+    let digits = s.trim_start_matches("0x");
+    u8::from_str_radix(digits, 16).map_err(|_| format!("invalid byte '{}'", s))
+}
+
+fn set(chip: &mut Chip8, reg: &str, value: &str) -> Result<String, String> {
+    let mut debug = chip.debug();
+    match reg.to_uppercase().as_str() {
+        "I" => debug.set_i(parse_addr(value)?),
+        "PC" => debug
+            .set_pc(parse_addr(value)?)
+            .map_err(|e| e.to_string())?,
+        "DT" => debug.set_delay(parse_byte(value)?),
+        "ST" => debug.set_sound(parse_byte(value)?),
+        r if r.len() == 2 && r.starts_with('V') => match u8::from_str_radix(&r[1..], 16) {
+            Ok(x) => debug
+                .set_register(x, parse_byte(value)?)
+                .map_err(|e| e.to_string())?,
+            Err(_) => return Err(format!("invalid register '{}'", reg)),
+        },
+        _ => return Err(format!("invalid register '{}'", reg)),
+    }
+    Ok(chip.registers().to_string())
+}
+
+/// Parses a 4 digit opcode where `.` matches any digit.
+fn parse_pattern(s: &str) -> Result<(u16, u16), String> {
+    let invalid = || format!("invalid opcode pattern '{}'", s);
+    if s.chars().count() != 4 {
+        return Err(invalid());
+    }
+    let (mut mask, mut value) = (0, 0);
+    for c in s.chars() {
+        mask <<= 4;
+        value <<= 4;
+        if c != '.' {
+            mask |= 0xF;
+            value |= c.to_digit(16).ok_or_else(invalid)? as u16;
+        }
+    }
+    Ok((mask, value))
+}
+
+fn stack(chip: &Chip8) -> String {
+    if chip.stack().is_empty() {
+        return "the stack is empty\n".to_string();
+    }
+    let mut out = String::new();
+    for (n, addr) in chip.stack().iter().enumerate().rev() {
+        writeln!(out, "{:X}: {:04X}", n, addr).unwrap();
+    }
+    out
+}
+
+fn memory(chip: &Chip8, addr: u16, len: usize) -> String {
+    let mem = chip.memory();
+    let start = (addr as usize).min(mem.len());
+    let end = (start + len).min(mem.len());
+    let mut out = String::new();
+    for (n, row) in mem[start..end].chunks(16).enumerate() {
+        let bytes: Vec<String> = row.iter().map(|b| format!("{:02X}", b)).collect();
+        writeln!(out, "{:04X}: {}", start + 16 * n, bytes.join(" ")).unwrap();
+    }
+    out
+}
+
+fn disassemble(chip: &Chip8, addr: u16, n: usize) -> String {
+    let mut out = String::new();
+    let mut addr = addr;
+    for _ in 0..n {
+        let opcode = match opcode_at(chip, addr) {
+            Some(opcode) => opcode,
+            None => break,
+        };
+        let marker = if addr == chip.pc() { '>' } else { ' ' };
+        let (text, len) = disasm::format_at(chip.memory(), addr as usize, Syntax::Cowgod)
+            .expect("the opcode is in memory");
+        writeln!(out, "{}{:04X}: {:04X}  {}", marker, addr, opcode, text).unwrap();
+        addr = match addr.checked_add(len as u16) {
+            Some(addr) => addr,
+            None => break,
+        };
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn chip(source: &str) -> Chip8 {
+        Chip8::new(asm::assemble(source).unwrap())
+    }
+
+    #[test]
+    fn breakpoint() {
+        let mut chip = chip("loop: ADD V0, 1\nADD V1, 1\nJP loop");
+        let mut debugger = Debugger::new();
+        assert_eq!(
+            debugger.command("break 202", &mut chip),
+            "1: break at 0202\n"
+        );
+        debugger.run_frame(&mut chip, 10).unwrap();
+        assert!(debugger.is_paused());
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.v()[0], 1);
+        assert_eq!(
+            debugger.take_message(),
+            Some("break at 0202 hit at 0202".to_string())
+        );
+        // continuing runs past the breakpoint until the next loop
+        debugger.command("continue", &mut chip);
+        debugger.run_frame(&mut chip, 10).unwrap();
+        assert_eq!(chip.pc(), 0x202);
+        assert_eq!(chip.v()[0], 2);
+        assert_eq!(chip.v()[1], 1);
+    }
+
+    #[test]
+    fn step() {
+        let mut chip = chip("LD V0, 5\nCALL sub\nsub: RET");
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        assert!(debugger.run_frame(&mut chip, 10).is_ok());
+        assert_eq!(chip.pc(), 0x200);
+        assert_eq!(
+            debugger.command("step", &mut chip),
+            ">0202: 2204  CALL 0x204\n"
+        );
+        // repeats the last command
+        assert_eq!(debugger.command("", &mut chip), ">0204: 00EE  RET\n");
+        assert_eq!(chip.stack(), &[0x202]);
+        assert_eq!(debugger.command("stack", &mut chip), "0: 0202\n");
+        assert!(debugger
+            .command("step 2", &mut chip)
+            .starts_with("error: stack underflow"));
+        assert!(debugger.is_paused());
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut chip = chip("LD V1, 1\nLD I, 0x300\nLD [I], V1\nLD V3, 7");
+        let mut debugger = Debugger::new();
+        debugger.command("watch 301 30F", &mut chip);
+        debugger.command("watch V3", &mut chip);
+        debugger.run_frame(&mut chip, 10).unwrap();
+        assert_eq!(
+            debugger.take_message(),
+            Some("watch 0301-030F hit at 0204: 000000000000000000000000000000 -> 010000000000000000000000000000".to_string())
+        );
+        debugger.command("delete 1", &mut chip);
+        debugger.resume();
+        debugger.run_frame(&mut chip, 10).unwrap();
+        assert_eq!(
+            debugger.take_message(),
+            Some("watch V3 hit at 0206: 00 -> 07".to_string())
+        );
+    }
+
+    #[test]
+    fn opcode_breakpoint() {
+        let mut chip = chip("CLS\nLD V0, 1\nDRW V0, V0, 1");
+        let mut debugger = Debugger::new();
+        assert_eq!(
+            debugger.command("breakop D...", &mut chip),
+            "1: break on opcode D...\n"
+        );
+        debugger.run_frame(&mut chip, 10).unwrap();
+        assert_eq!(chip.pc(), 0x204);
+        assert!(debugger
+            .command("breakop D..", &mut chip)
+            .starts_with("error"));
+    }
+
+    #[test]
+    fn dumps() {
+        let mut chip = chip("LD V0, 0xAB\nLD I, 0x123");
+        let mut debugger = Debugger::new();
+        debugger.command("step 2", &mut chip);
+        assert_eq!(
+            debugger.command("regs", &mut chip),
+            "V0=AB V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+             V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00\n\
+             I=0123 PC=0204 SP=0 DT=00 ST=00\n"
+        );
+        assert_eq!(
+            debugger.command("mem 200 4", &mut chip),
+            "0200: 60 AB A1 23\n"
+        );
+        assert_eq!(
+            debugger.command("dis 200 2", &mut chip),
+            " 0200: 60AB  LD V0, 0xAB\n 0202: A123  LD I, 0x123\n"
+        );
+        assert!(debugger
+            .command("frobnicate", &mut chip)
+            .starts_with("error"));
+    }
+
+    #[test]
+    fn mutators() {
+        let mut chip = chip("CLS");
+        let mut debugger = Debugger::new();
+        debugger.command("set V3 2A", &mut chip);
+        debugger.command("set pc 300", &mut chip);
+        assert_eq!(
+            debugger.command("poke 300 73 01", &mut chip),
+            "0300: 73 01\n"
+        );
+        debugger.command("step", &mut chip);
+        assert_eq!(chip.v()[3], 0x2B);
+        assert_eq!(chip.pc(), 0x302);
+        assert!(debugger
+            .command("set V3 100", &mut chip)
+            .starts_with("error"));
+        assert!(debugger.command("set VG 1", &mut chip).starts_with("error"));
+        assert!(debugger
+            .command("poke FFF 1 2", &mut chip)
+            .starts_with("error"));
+    }
+}
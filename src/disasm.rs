@@ -0,0 +1,330 @@
+//! Listings of CHIP-8, SUPER-CHIP and XO-CHIP ROMs in either Octo or Cowgod
+//! syntax.
+
+use crate::{decode, Instruction};
+use std::collections::BTreeSet;
+use std::fmt;
+
+/// The assembly dialect used to print instructions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Syntax {
+    /// The mnemonics from Cowgod's CHIP-8 technical reference.
+    Cowgod,
+    /// The syntax of the Octo assembler.
+    Octo,
+}
+
+impl Instruction {
+    /// Formats the instruction in the given syntax. `F000 NNNN` is printed
+    /// without its address, see `disassemble` for a full listing.
+    pub fn format(&self, syntax: Syntax) -> String {
+        self.format_with(syntax, &|addr| format!("0x{:03X}", addr))
+    }
+
+    fn format_with(&self, syntax: Syntax, addr: &dyn Fn(u16) -> String) -> String {
+        match syntax {
+            Syntax::Cowgod => self.cowgod(addr),
+            Syntax::Octo => self.octo(addr),
+        }
+    }
+
+    fn cowgod(&self, addr: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        match *self {
+            Sys(a) => format!("SYS {}", addr(a)),
+            Cls => "CLS".to_string(),
+            Ret => "RET".to_string(),
+            ScrollDown(n) => format!("SCD {}", n),
+            ScrollUp(n) => format!("SCU {}", n),
+            ScrollRight => "SCR".to_string(),
+            ScrollLeft => "SCL".to_string(),
+            Exit => "EXIT".to_string(),
+            Low => "LOW".to_string(),
+            High => "HIGH".to_string(),
+            Jp(a) => format!("JP {}", addr(a)),
+            Call(a) => format!("CALL {}", addr(a)),
+            SeByte { x, byte } => format!("SE V{:X}, 0x{:02X}", x, byte),
+            SneByte { x, byte } => format!("SNE V{:X}, 0x{:02X}", x, byte),
+            SeReg { x, y } => format!("SE V{:X}, V{:X}", x, y),
+            SaveRange { x, y } => format!("SAVE V{:X}, V{:X}", x, y),
+            LoadRange { x, y } => format!("LOAD V{:X}, V{:X}", x, y),
+            LdByte { x, byte } => format!("LD V{:X}, 0x{:02X}", x, byte),
+            AddByte { x, byte } => format!("ADD V{:X}, 0x{:02X}", x, byte),
+            Ld { x, y } => format!("LD V{:X}, V{:X}", x, y),
+            Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+            And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+            Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+            Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+            Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+            Shr { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+            Subn { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+            Shl { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+            SneReg { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+            LdI(a) => format!("LD I, {}", addr(a)),
+            JpV0(a) => format!("JP V0, {}", addr(a)),
+            Rnd { x, byte } => format!("RND V{:X}, 0x{:02X}", x, byte),
+            Drw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => format!("SKP V{:X}", x),
+            Sknp(x) => format!("SKNP V{:X}", x),
+            LdILong => "LD I, LONG".to_string(),
+            Plane(n) => format!("PLANE {}", n),
+            Audio => "AUDIO".to_string(),
+            LdVxDt(x) => format!("LD V{:X}, DT", x),
+            LdVxK(x) => format!("LD V{:X}, K", x),
+            LdDtVx(x) => format!("LD DT, V{:X}", x),
+            LdStVx(x) => format!("LD ST, V{:X}", x),
+            AddI(x) => format!("ADD I, V{:X}", x),
+            LdF(x) => format!("LD F, V{:X}", x),
+            LdHf(x) => format!("LD HF, V{:X}", x),
+            LdB(x) => format!("LD B, V{:X}", x),
+            Pitch(x) => format!("PITCH V{:X}", x),
+            LdIVx(x) => format!("LD [I], V{:X}", x),
+            LdVxI(x) => format!("LD V{:X}, [I]", x),
+            LdRVx(x) => format!("LD R, V{:X}", x),
+            LdVxR(x) => format!("LD V{:X}, R", x),
+        }
+    }
+
+    fn octo(&self, addr: &dyn Fn(u16) -> String) -> String {
+        use Instruction::*;
+        match *self {
+            Sys(a) => format!("0x{:02X} 0x{:02X}", a >> 8, a & 0xFF),
+            Cls => "clear".to_string(),
+            Ret => "return".to_string(),
+            ScrollDown(n) => format!("scroll-down {}", n),
+            ScrollUp(n) => format!("scroll-up {}", n),
+            ScrollRight => "scroll-right".to_string(),
+            ScrollLeft => "scroll-left".to_string(),
+            Exit => "exit".to_string(),
+            Low => "lores".to_string(),
+            High => "hires".to_string(),
+            Jp(a) => format!("jump {}", addr(a)),
+            Call(a) => format!(":call {}", addr(a)),
+            // Octo conditions describe when the next instruction runs
+            SeByte { x, byte } => format!("if v{:x} != 0x{:02X} then", x, byte),
+            SneByte { x, byte } => format!("if v{:x} == 0x{:02X} then", x, byte),
+            SeReg { x, y } => format!("if v{:x} != v{:x} then", x, y),
+            SaveRange { x, y } => format!("save v{:x} - v{:x}", x, y),
+            LoadRange { x, y } => format!("load v{:x} - v{:x}", x, y),
+            LdByte { x, byte } => format!("v{:x} := 0x{:02X}", x, byte),
+            AddByte { x, byte } => format!("v{:x} += 0x{:02X}", x, byte),
+            Ld { x, y } => format!("v{:x} := v{:x}", x, y),
+            Or { x, y } => format!("v{:x} |= v{:x}", x, y),
+            And { x, y } => format!("v{:x} &= v{:x}", x, y),
+            Xor { x, y } => format!("v{:x} ^= v{:x}", x, y),
+            Add { x, y } => format!("v{:x} += v{:x}", x, y),
+            Sub { x, y } => format!("v{:x} -= v{:x}", x, y),
+            Shr { x, y } => format!("v{:x} >>= v{:x}", x, y),
+            Subn { x, y } => format!("v{:x} =- v{:x}", x, y),
+            Shl { x, y } => format!("v{:x} <<= v{:x}", x, y),
+            SneReg { x, y } => format!("if v{:x} == v{:x} then", x, y),
+            LdI(a) => format!("i := {}", addr(a)),
+            JpV0(a) => format!("jump0 {}", addr(a)),
+            Rnd { x, byte } => format!("v{:x} := random 0x{:02X}", x, byte),
+            Drw { x, y, n } => format!("sprite v{:x} v{:x} {}", x, y, n),
+            Skp(x) => format!("if v{:x} -key then", x),
+            Sknp(x) => format!("if v{:x} key then", x),
+            LdILong => "i := long".to_string(),
+            Plane(n) => format!("plane {}", n),
+            Audio => "audio".to_string(),
+            LdVxDt(x) => format!("v{:x} := delay", x),
+            LdVxK(x) => format!("v{:x} := key", x),
+            LdDtVx(x) => format!("delay := v{:x}", x),
+            LdStVx(x) => format!("buzzer := v{:x}", x),
+            AddI(x) => format!("i += v{:x}", x),
+            LdF(x) => format!("i := hex v{:x}", x),
+            LdHf(x) => format!("i := bighex v{:x}", x),
+            LdB(x) => format!("bcd v{:x}", x),
+            Pitch(x) => format!("pitch := v{:x}", x),
+            LdIVx(x) => format!("save v{:x}", x),
+            LdVxI(x) => format!("load v{:x}", x),
+            LdRVx(x) => format!("saveflags v{:x}", x),
+            LdVxR(x) => format!("loadflags v{:x}", x),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.format(Syntax::Cowgod))
+    }
+}
+
+/// One line of a listing: an instruction or some bytes that aren't one.
+struct Line {
+    addr: u16,
+    bytes: Vec<u8>,
+    instruction: Option<Instruction>,
+}
+
+impl Line {
+    /// Decodes the line starting at `offset` in `rom`, loaded at `addr`.
+    fn at(rom: &[u8], offset: usize, addr: u16) -> Line {
+        let word = match rom.get(offset..offset + 2) {
+            Some(word) => word,
+            None => {
+                return Line {
+                    addr,
+                    bytes: rom[offset..].to_vec(),
+                    instruction: None,
+                }
+            }
+        };
+        let instruction = decode(((word[0] as u16) << 8) | word[1] as u16).ok();
+        let len = match instruction {
+            Some(Instruction::LdILong) if offset + 4 <= rom.len() => 4,
+            _ => 2,
+        };
+        Line {
+            addr,
+            bytes: rom[offset..offset + len].to_vec(),
+            instruction,
+        }
+    }
+
+    fn text(&self, syntax: Syntax, addr: &dyn Fn(u16) -> String) -> String {
+        match self.instruction {
+            Some(Instruction::LdILong) if self.bytes.len() == 4 => {
+                let long = ((self.bytes[2] as u16) << 8) | self.bytes[3] as u16;
+                match syntax {
+                    Syntax::Cowgod => format!("LD I, LONG 0x{:04X}", long),
+                    Syntax::Octo => format!("i := long 0x{:04X}", long),
+                }
+            }
+            Some(instruction) => instruction.format_with(syntax, addr),
+            None => {
+                let data: Vec<String> = self.bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+                match syntax {
+                    Syntax::Cowgod => format!("DB {}", data.join(", ")),
+                    Syntax::Octo => data.join(" "),
+                }
+            }
+        }
+    }
+}
+
+fn lines(rom: &[u8], origin: u16) -> Vec<Line> {
+    let mut lines = vec![];
+    let mut offset = 0;
+    while offset < rom.len() {
+        let line = Line::at(rom, offset, origin.wrapping_add(offset as u16));
+        offset += line.bytes.len();
+        lines.push(line);
+    }
+    lines
+}
+
+/// Formats the instruction at `addr` in `memory`, and returns it with its
+/// length in bytes. Words that aren't instructions are printed as data.
+pub fn format_at(memory: &[u8], addr: usize, syntax: Syntax) -> Option<(String, usize)> {
+    if addr >= memory.len() {
+        return None;
+    }
+    let line = Line::at(memory, addr, addr as u16);
+    let text = line.text(syntax, &|a| format!("0x{:03X}", a));
+    Some((text, line.bytes.len()))
+}
+
+fn label(addr: u16) -> String {
+    format!("label_{:03X}", addr)
+}
+
+/// Produces a listing of `rom` loaded at `origin`, with one line per
+/// instruction showing its address and raw bytes. Jump and call targets get a
+/// generated label. Words that aren't instructions are printed as data.
+pub fn disassemble(rom: &[u8], origin: u16, syntax: Syntax) -> String {
+    let lines = lines(rom, origin);
+    let starts: BTreeSet<u16> = lines.iter().map(|line| line.addr).collect();
+    let targets: BTreeSet<u16> = lines
+        .iter()
+        .filter_map(|line| line.instruction.and_then(|i| i.target()))
+        .filter(|addr| starts.contains(addr))
+        .collect();
+    let addr = |a: u16| {
+        if targets.contains(&a) {
+            label(a)
+        } else {
+            format!("0x{:03X}", a)
+        }
+    };
+    let mut out = String::new();
+    for line in lines {
+        if targets.contains(&line.addr) {
+            match syntax {
+                Syntax::Cowgod => out.push_str(&format!("{}:\n", label(line.addr))),
+                Syntax::Octo => out.push_str(&format!(": {}\n", label(line.addr))),
+            }
+        }
+        let raw: Vec<String> = line.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        let text = line.text(syntax, &addr);
+        out.push_str(&format!(
+            "{:04X}: {:<9} {}\n",
+            line.addr,
+            raw.join(" "),
+            text
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats() {
+        let drw = Instruction::Drw { x: 1, y: 2, n: 5 };
+        assert_eq!(drw.format(Syntax::Cowgod), "DRW V1, V2, 5");
+        assert_eq!(drw.format(Syntax::Octo), "sprite v1 v2 5");
+        let se = Instruction::SeByte { x: 0xA, byte: 3 };
+        assert_eq!(se.to_string(), "SE VA, 0x03");
+        assert_eq!(se.format(Syntax::Octo), "if va != 0x03 then");
+        assert_eq!(Instruction::LdIVx(4).to_string(), "LD [I], V4");
+        assert_eq!(Instruction::LdI(0x22A).format(Syntax::Octo), "i := 0x22A");
+    }
+
+    #[test]
+    fn format_at() {
+        let memory = [0xF0, 0x00, 0x12, 0x34, 0x00, 0xEE, 0xFF];
+        assert_eq!(
+            super::format_at(&memory, 0, Syntax::Cowgod),
+            Some(("LD I, LONG 0x1234".to_string(), 4))
+        );
+        assert_eq!(
+            super::format_at(&memory, 4, Syntax::Octo),
+            Some(("return".to_string(), 2))
+        );
+        assert_eq!(
+            super::format_at(&memory, 6, Syntax::Cowgod),
+            Some(("DB 0xFF".to_string(), 1))
+        );
+        assert_eq!(super::format_at(&memory, 7, Syntax::Cowgod), None);
+    }
+
+    #[test]
+    fn listing() {
+        let rom = [0x60, 0x01, 0x22, 0x06, 0x12, 0x02, 0x00, 0xEE, 0xFF];
+        assert_eq!(
+            disassemble(&rom, 0x200, Syntax::Cowgod),
+            "0200: 60 01     LD V0, 0x01\n\
+             label_202:\n\
+             0202: 22 06     CALL label_206\n\
+             0204: 12 02     JP label_202\n\
+             label_206:\n\
+             0206: 00 EE     RET\n\
+             0208: FF        DB 0xFF\n"
+        );
+    }
+
+    #[test]
+    fn listing_octo() {
+        let rom = [0xF0, 0x00, 0x12, 0x34, 0x12, 0x00, 0xFF, 0xFF];
+        assert_eq!(
+            disassemble(&rom, 0x200, Syntax::Octo),
+            ": label_200\n\
+             0200: F0 00 12 34 i := long 0x1234\n\
+             0204: 12 00     jump label_200\n\
+             0206: FF FF     0xFF 0xFF\n"
+        );
+    }
+}
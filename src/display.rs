@@ -0,0 +1,259 @@
+use crate::state::{Reader, StateError, Writer};
+
+const LORES: (usize, usize) = (64, 32);
+const HIRES: (usize, usize) = (128, 64);
+
+/// Number of bit planes. Only XO-CHIP programs can draw to the second one.
+pub const PLANES: usize = 2;
+
+/// The framebuffer. In low resolution mode it is 64x32 pixels, in high
+/// resolution mode (SUPER-CHIP) 128x64.
+///
+/// Each pixel has one bit per plane, which gives four colours in XO-CHIP.
+/// Drawing, clearing and scrolling only affect the selected planes.
+#[derive(Clone)]
+pub struct Screen {
+    hires: bool,
+    selected: u8,
+    // pixel (x, y) is bit `127 - x` of `planes[plane][y]`
+    planes: [[u128; 64]; PLANES],
+}
+
+impl Default for Screen {
+    fn default() -> Screen {
+        Screen {
+            hires: false,
+            selected: 0x1,
+            planes: [[0; 64]; PLANES],
+        }
+    }
+}
+
+impl Screen {
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES.0
+        } else {
+            LORES.0
+        }
+    }
+
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES.1
+        } else {
+            LORES.1
+        }
+    }
+
+    /// Returns the colour of pixel (x, y), with bit `n` set if the pixel is
+    /// set in plane `n`.
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        (0..PLANES).fold(0, |colour, plane| {
+            colour | ((((self.planes[plane][y] >> (127 - x)) & 0x1) as u8) << plane)
+        })
+    }
+
+    /// Returns row `y` of the first plane, see `Screen::plane_row`.
+    pub fn row(&self, y: usize) -> u128 {
+        self.plane_row(0, y)
+    }
+
+    /// Returns row `y` of `plane` as a `width()` bit number whose most
+    /// significant bit is the leftmost pixel.
+    pub fn plane_row(&self, plane: usize, y: usize) -> u128 {
+        self.planes[plane][y] >> (128 - self.width())
+    }
+
+    /// Bit mask of the planes affected by drawing, clearing and scrolling.
+    pub fn selected_planes(&self) -> u8 {
+        self.selected
+    }
+
+    pub(crate) fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & ((1 << PLANES) - 1);
+    }
+
+    #[cfg(test)]
+    pub(crate) fn set_row(&mut self, y: usize, bits: u128) {
+        self.planes[0][y] = bits << (128 - self.width());
+    }
+
+    fn selected_mut(&mut self) -> impl Iterator<Item = &mut [u128; 64]> {
+        let selected = self.selected;
+        self.planes
+            .iter_mut()
+            .enumerate()
+            .filter(move |(plane, _)| selected & (1 << plane) != 0)
+            .map(|(_, rows)| rows)
+    }
+
+    pub(crate) fn save(&self, w: &mut Writer) {
+        w.u8(self.hires as u8);
+        w.u8(self.selected);
+        for rows in self.planes.iter() {
+            for row in rows.iter() {
+                w.u128(*row);
+            }
+        }
+    }
+
+    pub(crate) fn load(r: &mut Reader) -> Result<Screen, StateError> {
+        let mut screen = Screen {
+            hires: r.bool()?,
+            ..Screen::default()
+        };
+        screen.select_planes(r.u8()?);
+        for rows in screen.planes.iter_mut() {
+            for row in rows.iter_mut() {
+                *row = r.u128()?;
+            }
+        }
+        Ok(screen)
+    }
+
+    /// Switches resolution. Like most modern interpreters, this also clears
+    /// all planes.
+    pub(crate) fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.planes = [[0; 64]; PLANES];
+    }
+
+    pub(crate) fn clear(&mut self) {
+        for rows in self.selected_mut() {
+            *rows = [0; 64];
+        }
+    }
+
+    /// XORs `bit` into pixel (x, y) of `plane`, returning whether the pixel
+    /// was erased.
+    pub(crate) fn set_pixel(&mut self, plane: usize, bit: u128, x: usize, y: usize) -> bool {
+        let rows = &mut self.planes[plane];
+        let mask = 1 << (127 - x);
+        let old_val = rows[y] & mask;
+        rows[y] ^= bit << (127 - x);
+        let new_val = rows[y] & mask;
+        old_val > 0 && new_val == 0
+    }
+
+    pub(crate) fn scroll_down(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for rows in self.selected_mut() {
+            rows.copy_within(0..height - n, n);
+            for row in &mut rows[..n] {
+                *row = 0;
+            }
+        }
+    }
+
+    pub(crate) fn scroll_up(&mut self, n: usize) {
+        let height = self.height();
+        let n = n.min(height);
+        for rows in self.selected_mut() {
+            rows.copy_within(n..height, 0);
+            for row in &mut rows[height - n..height] {
+                *row = 0;
+            }
+        }
+    }
+
+    pub(crate) fn scroll_left(&mut self, n: usize) {
+        for rows in self.selected_mut() {
+            for row in &mut rows[..] {
+                *row = row.checked_shl(n as u32).unwrap_or(0);
+            }
+        }
+    }
+
+    pub(crate) fn scroll_right(&mut self, n: usize) {
+        let mask = !0u128 << (128 - self.width());
+        for rows in self.selected_mut() {
+            for row in &mut rows[..] {
+                *row = row.checked_shr(n as u32).unwrap_or(0) & mask;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolution() {
+        let mut screen = Screen::default();
+        assert_eq!((screen.width(), screen.height()), (64, 32));
+        screen.set_pixel(0, 1, 0, 0);
+        screen.set_hires(true);
+        assert_eq!((screen.width(), screen.height()), (128, 64));
+        assert_eq!(screen.pixel(0, 0), 0);
+    }
+
+    #[test]
+    fn row() {
+        let mut screen = Screen::default();
+        screen.set_pixel(0, 1, 0, 3);
+        screen.set_pixel(0, 1, 63, 3);
+        assert_eq!(screen.row(3), 0x8000000000000001);
+        screen.set_hires(true);
+        screen.set_pixel(0, 1, 127, 3);
+        assert_eq!(screen.row(3), 0x1);
+    }
+
+    #[test]
+    fn scroll_down() {
+        let mut screen = Screen::default();
+        screen.set_row(0, 0xF0);
+        screen.set_row(31, 0xF);
+        screen.scroll_down(2);
+        assert_eq!(screen.row(0), 0x0);
+        assert_eq!(screen.row(2), 0xF0);
+        assert_eq!(screen.row(31), 0x0);
+    }
+
+    #[test]
+    fn scroll_sideways() {
+        let mut screen = Screen::default();
+        screen.set_row(0, 0xF00000000000000F);
+        screen.scroll_right(4);
+        assert_eq!(screen.row(0), 0x0F00000000000000);
+        screen.scroll_left(8);
+        assert_eq!(screen.row(0), 0x0000000000000000);
+        screen.set_row(0, 0xF00000000000000F);
+        screen.scroll_left(4);
+        assert_eq!(screen.row(0), 0x00000000000000F0);
+    }
+
+    #[test]
+    fn scroll_up() {
+        let mut screen = Screen::default();
+        screen.set_row(0, 0xF0);
+        screen.set_row(31, 0xF);
+        screen.scroll_up(1);
+        assert_eq!(screen.row(0), 0x0);
+        assert_eq!(screen.row(30), 0xF);
+        assert_eq!(screen.row(31), 0x0);
+    }
+
+    #[test]
+    fn planes() {
+        let mut screen = Screen::default();
+        screen.set_pixel(0, 1, 1, 0);
+        screen.set_pixel(1, 1, 2, 0);
+        screen.set_pixel(0, 1, 3, 0);
+        screen.set_pixel(1, 1, 3, 0);
+        assert_eq!(screen.pixel(0, 0), 0);
+        assert_eq!(screen.pixel(1, 0), 1);
+        assert_eq!(screen.pixel(2, 0), 2);
+        assert_eq!(screen.pixel(3, 0), 3);
+        screen.select_planes(0x2);
+        screen.clear();
+        assert_eq!(screen.plane_row(0, 0), 0x5000000000000000);
+        assert_eq!(screen.plane_row(1, 0), 0x0);
+    }
+}
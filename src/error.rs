@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fmt;
+
+/// The result of successfully executing a single `Chip8::step`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    /// An instruction was executed.
+    Executed,
+    /// The interpreter is blocked on `FX0A` until a key is pressed.
+    WaitingForKey,
+    /// The interpreter is blocked after `DXYN` until the next timer tick.
+    WaitingForVBlank,
+    /// The program exited with `00FD`.
+    Exited,
+}
+
+/// Errors raised by the interpreter when a program does something the
+/// machine cannot do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// The word `opcode` found at `addr` is not a valid instruction.
+    InvalidOpcode { addr: u16, opcode: u16 },
+    /// The instruction at `addr` tried to call a subroutine with a full stack.
+    StackOverflow { addr: u16 },
+    /// The instruction at `addr` tried to return with an empty stack.
+    StackUnderflow { addr: u16 },
+    /// The instruction at `addr` tried to access memory at `target`, which is
+    /// outside of the address space.
+    MemoryOutOfBounds { addr: u16, target: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Chip8Error::InvalidOpcode { addr, opcode } => {
+                write!(f, "invalid opcode {:04X} at {:03X}", opcode, addr)
+            }
+            Chip8Error::StackOverflow { addr } => write!(f, "stack overflow at {:03X}", addr),
+            Chip8Error::StackUnderflow { addr } => write!(f, "stack underflow at {:03X}", addr),
+            Chip8Error::MemoryOutOfBounds { addr, target } => write!(
+                f,
+                "out of bounds memory access to {:X} at {:03X}",
+                target, addr
+            ),
+        }
+    }
+}
+
+impl Error for Chip8Error {}
+
+/// Errors raised while loading a program into a new machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadError {
+    /// The program is `size` bytes long, but at most `max` bytes fit in memory.
+    RomTooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::RomTooLarge { size, max } => write!(
+                f,
+                "ROM is {} bytes long, but at most {} bytes fit in memory",
+                size, max
+            ),
+        }
+    }
+}
+
+impl Error for LoadError {}
@@ -0,0 +1,157 @@
+//! Exporting the screen as PNG images and text, for bug reports and tests.
+
+use crate::Screen;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+/// RGB colours indexed by the plane bits of a pixel, see `Screen::pixel`.
+pub type Palette = [[u8; 3]; 4];
+
+/// Black and white, with greys for the XO-CHIP colours as in Octo.
+pub const DEFAULT_PALETTE: Palette = [[0, 0, 0], [255, 255, 255], [170, 170, 170], [85, 85, 85]];
+
+/// Characters for `to_text` that only use ASCII, which diffs well.
+pub const ASCII_CHARS: [char; 4] = ['.', '#', '+', '*'];
+
+/// Characters for `to_text` that look like the screen in a terminal.
+pub const BLOCK_CHARS: [char; 4] = [' ', '█', '▓', '▒'];
+
+/// Parses an `RRGGBB` colour, with or without a leading `#`.
+pub fn parse_colour(colour: &str) -> Option<[u8; 3]> {
+    let hex = colour.trim().trim_start_matches('#');
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
+
+/// Parses four comma separated `RRGGBB` colours, e.g.
+/// `000000,FFFFFF,AAAAAA,555555`.
+pub fn parse_palette(palette: &str) -> Option<Palette> {
+    let mut colours = [[0; 3]; 4];
+    let mut parts = palette.split(',');
+    for colour in colours.iter_mut() {
+        *colour = parse_colour(parts.next()?)?;
+    }
+    match parts.next() {
+        Some(_) => None,
+        None => Some(colours),
+    }
+}
+
+/// An RGB image, 3 bytes per pixel row by row.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl Image {
+    /// Renders `screen` with each pixel as a `scale` x `scale` square.
+    pub fn render(screen: &Screen, scale: usize, palette: &Palette) -> Image {
+        let (width, height) = (screen.width() * scale, screen.height() * scale);
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend_from_slice(&palette[screen.pixel(x / scale, y / scale) as usize]);
+            }
+        }
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn write_png<W: Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.pixels)
+    }
+
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        self.write_png(BufWriter::new(File::create(path)?))
+    }
+}
+
+/// Renders `screen` as text, one line per row and one character per pixel
+/// taken from `chars`, which is indexed like a `Palette`.
+pub fn to_text(screen: &Screen, chars: &[char; 4]) -> String {
+    let mut out = String::new();
+    for y in 0..screen.height() {
+        out.extend((0..screen.width()).map(|x| chars[screen.pixel(x, y) as usize]));
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, Chip8};
+
+    fn screen() -> Screen {
+        // the top left corner of the 0 sprite
+        let mut chip = Chip8::new(asm::assemble("LD I, 0\nDRW V0, V0, 2").unwrap());
+        chip.step().unwrap();
+        chip.step().unwrap();
+        chip.screen().clone()
+    }
+
+    #[test]
+    fn palette() {
+        assert_eq!(
+            parse_palette("000000,FFFFFF,#aaaaaa, 555555"),
+            Some(DEFAULT_PALETTE)
+        );
+        assert_eq!(parse_palette("000000,FFFFFF,AAAAAA"), None);
+        assert_eq!(parse_palette("000000,FFFFFF,AAAAAA,555555,000000"), None);
+        assert_eq!(parse_palette("000000,FFFFFF,AAAAAA,55555"), None);
+    }
+
+    #[test]
+    fn render() {
+        let image = Image::render(&screen(), 2, &DEFAULT_PALETTE);
+        assert_eq!((image.width, image.height), (128, 64));
+        let pixel = |x: usize, y: usize| &image.pixels[(y * 128 + x) * 3..][..3];
+        assert_eq!(pixel(0, 0), &[255, 255, 255]);
+        assert_eq!(pixel(7, 3), &[255, 255, 255]);
+        assert_eq!(pixel(8, 0), &[0, 0, 0]);
+        assert_eq!(pixel(0, 2), &[255, 255, 255]);
+        assert_eq!(pixel(2, 3), &[0, 0, 0]);
+    }
+
+    #[test]
+    fn png() {
+        let mut data = vec![];
+        Image::render(&screen(), 1, &DEFAULT_PALETTE)
+            .write_png(&mut data)
+            .unwrap();
+        assert_eq!(&data[..8], b"\x89PNG\r\n\x1a\n");
+        let decoder = png::Decoder::new(&data[..]);
+        let (info, mut reader) = decoder.read_info().unwrap();
+        assert_eq!((info.width, info.height), (64, 32));
+        let mut pixels = vec![0; info.buffer_size()];
+        reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(
+            &pixels[..15],
+            &[255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 255, 0, 0, 0]
+        );
+        assert_eq!(&pixels[64 * 3..64 * 3 + 6], &[255, 255, 255, 0, 0, 0]);
+    }
+
+    #[test]
+    fn text() {
+        let text = to_text(&screen(), &ASCII_CHARS);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 32);
+        assert_eq!(lines[0], format!("####{}", ".".repeat(60)));
+        assert_eq!(lines[1], format!("#..#{}", ".".repeat(60)));
+        assert_eq!(lines[2], ".".repeat(64));
+        assert!(to_text(&screen(), &BLOCK_CHARS).starts_with("████ "));
+    }
+}
@@ -0,0 +1,315 @@
+//! Running ROMs without a display, for regression tests.
+//!
+//! A `Runner` runs a machine frame by frame, pressing and releasing keys as
+//! told by a `KeyScript`, until a frame limit or one of its stop conditions
+//! is reached.
+
+use crate::{Chip8, Chip8Error, StepOutcome};
+use std::error::Error;
+use std::fmt;
+
+/// When to stop running before the frame limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Condition {
+    /// A jump to itself, the usual way for a test ROM to end.
+    PcLoop,
+    /// The byte at `addr` is `value`.
+    Memory { addr: u16, value: u8 },
+}
+
+/// Why a `Runner` stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    FrameLimit,
+    Loop { addr: u16 },
+    Memory { addr: u16, value: u8 },
+    Exited,
+    Error(Chip8Error),
+}
+
+impl fmt::Display for Stop {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Stop::FrameLimit => write!(f, "reached the frame limit"),
+            Stop::Loop { addr } => write!(f, "stuck in a loop at {:03X}", addr),
+            Stop::Memory { addr, value } => write!(f, "memory at {:03X} is {:02X}", addr, value),
+            Stop::Exited => write!(f, "the program exited"),
+            Stop::Error(e) => write!(f, "emulation stopped: {}", e),
+        }
+    }
+}
+
+/// A key press or release.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEvent {
+    /// The frame at the start of which the event happens, counting from 0.
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Error raised by `KeyScript::parse` on an entry it doesn't understand.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScriptError {
+    pub entry: String,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid key event '{}', expected FRAME:KEY, FRAME:+KEY or FRAME:-KEY",
+            self.entry
+        )
+    }
+}
+
+impl Error for ScriptError {}
+
+/// Key events sorted by frame.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyScript {
+    events: Vec<KeyEvent>,
+}
+
+impl KeyScript {
+    /// Parses entries separated by spaces or commas. `FRAME:+KEY` presses a
+    /// key, `FRAME:-KEY` releases it and `FRAME:KEY` presses it for one
+    /// frame. Frames are decimal and keys hexadecimal, e.g. `60:5 90:+A 95:-A`.
+    pub fn parse(script: &str) -> Result<KeyScript, ScriptError> {
+        let mut events = vec![];
+        for entry in script.split(|c: char| c == ',' || c.is_whitespace()) {
+            if entry.is_empty() {
+                continue;
+            }
+            let error = || ScriptError {
+                entry: entry.to_string(),
+            };
+            let mut parts = entry.splitn(2, ':');
+            let frame: u64 = parts.next().unwrap().parse().map_err(|_| error())?;
+            let key = parts.next().ok_or_else(error)?;
+            let (press, release, key) = match key.as_bytes().first() {
+                Some(b'+') => (true, false, &key[1..]),
+                Some(b'-') => (false, true, &key[1..]),
+                _ => (true, true, key),
+            };
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(key) if key < 16 => key,
+                _ => return Err(error()),
+            };
+            if press {
+                events.push(KeyEvent {
+                    frame,
+                    key,
+                    pressed: true,
+                });
+            }
+            if release {
+                events.push(KeyEvent {
+                    frame: if press { frame + 1 } else { frame },
+                    key,
+                    pressed: false,
+                });
+            }
+        }
+        // stable, so events on the same frame keep their order
+        events.sort_by_key(|e| e.frame);
+        Ok(KeyScript { events })
+    }
+
+    /// A script made of `events`, which must be sorted by frame.
+    pub fn from_events(events: Vec<KeyEvent>) -> KeyScript {
+        KeyScript { events }
+    }
+
+    pub fn events(&self) -> &[KeyEvent] {
+        &self.events
+    }
+}
+
+/// The result of `Runner::run`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Report {
+    /// The number of frames run, including the one it stopped in.
+    pub frames: u64,
+    pub stop: Stop,
+}
+
+/// Runs a machine without a display, see the module documentation.
+pub struct Runner {
+    cycles: usize,
+    max_frames: u64,
+    conditions: Vec<Condition>,
+    keys: KeyScript,
+}
+
+impl Runner {
+    /// Runs `cycles` instructions per frame for at most `max_frames` frames.
+    pub fn new(cycles: usize, max_frames: u64) -> Runner {
+        Runner {
+            cycles,
+            max_frames,
+            conditions: vec![],
+            keys: KeyScript::default(),
+        }
+    }
+
+    pub fn add_condition(&mut self, condition: Condition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn set_keys(&mut self, keys: KeyScript) {
+        self.keys = keys;
+    }
+
+    /// Runs `chip` until the frame limit or a stop condition. The conditions
+    /// are checked after every instruction.
+    pub fn run(&self, chip: &mut Chip8) -> Report {
+        let mut events = self.keys.events().iter().peekable();
+        for frame in 0..self.max_frames {
+            while let Some(event) = events.next_if(|e| e.frame <= frame) {
+                if event.pressed {
+                    chip.press_key(event.key);
+                } else {
+                    chip.release_key(event.key);
+                }
+            }
+            if let Some(stop) = self.run_frame(chip) {
+                return Report {
+                    frames: frame + 1,
+                    stop,
+                };
+            }
+        }
+        Report {
+            frames: self.max_frames,
+            stop: Stop::FrameLimit,
+        }
+    }
+
+    /// Like `Chip8::run_frame`, but checks the conditions.
+    fn run_frame(&self, chip: &mut Chip8) -> Option<Stop> {
+        for _ in 0..self.cycles {
+            let pc = chip.pc();
+            match chip.step() {
+                Ok(StepOutcome::Executed) => {}
+                Ok(StepOutcome::Exited) => return Some(Stop::Exited),
+                Ok(StepOutcome::WaitingForKey) | Ok(StepOutcome::WaitingForVBlank) => break,
+                Err(e) => return Some(Stop::Error(e)),
+            }
+            if let Some(stop) = self.check(chip, pc) {
+                return Some(stop);
+            }
+        }
+        chip.tick_timers();
+        None
+    }
+
+    fn check(&self, chip: &Chip8, pc: u16) -> Option<Stop> {
+        self.conditions
+            .iter()
+            .find_map(|condition| match *condition {
+                Condition::PcLoop if chip.pc() == pc => Some(Stop::Loop { addr: pc }),
+                Condition::Memory { addr, value }
+                    if chip.memory().get(addr as usize) == Some(&value) =>
+                {
+                    Some(Stop::Memory { addr, value })
+                }
+                _ => None,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{asm, Platform};
+
+    fn chip(source: &str) -> Chip8 {
+        Chip8::new(asm::assemble(source).unwrap())
+    }
+
+    #[test]
+    fn script() {
+        let script = KeyScript::parse("2:5, 0:+A\n3:-a").unwrap();
+        let events: Vec<(u64, u8, bool)> = script
+            .events()
+            .iter()
+            .map(|e| (e.frame, e.key, e.pressed))
+            .collect();
+        assert_eq!(
+            events,
+            vec![(0, 0xA, true), (2, 5, true), (3, 5, false), (3, 0xA, false)]
+        );
+        for bad in &["5", "x:1", "1:G", "1:10", "1:+"] {
+            assert_eq!(
+                KeyScript::parse(bad),
+                Err(ScriptError {
+                    entry: bad.to_string()
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn frame_limit() {
+        let mut chip = chip("loop: ADD V0, 1\nJP loop");
+        let report = Runner::new(10, 3).run(&mut chip);
+        assert_eq!(
+            report,
+            Report {
+                frames: 3,
+                stop: Stop::FrameLimit
+            }
+        );
+        assert_eq!(chip.v()[0], 15);
+    }
+
+    #[test]
+    fn conditions() {
+        let mut runner = Runner::new(10, 100);
+        runner.add_condition(Condition::PcLoop);
+        let report = runner.run(&mut chip("LD V0, 1\nCLS\nend: JP end"));
+        assert_eq!(report.stop, Stop::Loop { addr: 0x204 });
+        assert_eq!(report.frames, 1);
+
+        let mut runner = Runner::new(1, 100);
+        runner.add_condition(Condition::Memory {
+            addr: 0x300,
+            value: 3,
+        });
+        let mut chip = chip("loop: ADD V0, 1\nLD I, 0x300\nLD [I], V0\nJP loop");
+        let report = runner.run(&mut chip);
+        assert_eq!(
+            report.stop,
+            Stop::Memory {
+                addr: 0x300,
+                value: 3
+            }
+        );
+        assert_eq!(report.frames, 11);
+    }
+
+    #[test]
+    fn keys() {
+        let mut runner = Runner::new(10, 100);
+        runner.add_condition(Condition::PcLoop);
+        runner.set_keys(KeyScript::parse("5:7").unwrap());
+        let mut chip = chip("LD V1, K\nend: JP end");
+        let report = runner.run(&mut chip);
+        assert_eq!(report.stop, Stop::Loop { addr: 0x202 });
+        assert_eq!(report.frames, 6);
+        assert_eq!(chip.v()[1], 7);
+    }
+
+    #[test]
+    fn errors() {
+        let report = Runner::new(10, 100).run(&mut chip("RET"));
+        assert_eq!(
+            report.stop,
+            Stop::Error(Chip8Error::StackUnderflow { addr: 0x200 })
+        );
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &[0x00, 0xFD]).unwrap();
+        assert_eq!(Runner::new(10, 100).run(&mut chip).stop, Stop::Exited);
+    }
+}
@@ -0,0 +1,168 @@
+//! Access to the internals of the machine for tools such as debuggers and
+//! tracers.
+//!
+//! The machine is read through accessors such as `Chip8::pc` or
+//! `Chip8::memory`. `Chip8::debug` gives a handle that can change the machine
+//! behind the program's back; it is meant for debugging only, as nothing
+//! keeps the program's invariants.
+
+use crate::Chip8;
+use std::error::Error;
+use std::fmt;
+
+/// Prints the registers of a machine, e.g.
+///
+/// ```text
+/// V0=00 V1=07 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00
+/// V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=01
+/// I=0123 PC=0204 SP=1 DT=00 ST=00
+/// ```
+pub struct Registers<'a>(&'a Chip8);
+
+impl<'a> fmt::Display for Registers<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let chip = self.0;
+        for (x, value) in chip.v().iter().enumerate() {
+            let sep = if x % 8 == 7 { '\n' } else { ' ' };
+            write!(f, "V{:X}={:02X}{}", x, value, sep)?;
+        }
+        writeln!(
+            f,
+            "I={:04X} PC={:04X} SP={:X} DT={:02X} ST={:02X}",
+            chip.i(),
+            chip.pc(),
+            chip.sp(),
+            chip.delay(),
+            chip.sound()
+        )
+    }
+}
+
+/// Errors raised by the debug API.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugError {
+    /// The address is outside of the address space.
+    AddressOutOfRange(usize),
+    /// There is no register VX with this X.
+    InvalidRegister(u8),
+}
+
+impl fmt::Display for DebugError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DebugError::AddressOutOfRange(addr) => write!(f, "address {:X} is out of range", addr),
+            DebugError::InvalidRegister(x) => write!(f, "there is no register V{:X}", x),
+        }
+    }
+}
+
+impl Error for DebugError {}
+
+/// Changes the machine for debugging, see the module documentation.
+pub struct DebugAccess<'a> {
+    chip: &'a mut Chip8,
+}
+
+impl<'a> DebugAccess<'a> {
+    /// Writes `bytes` starting at `addr`. Nothing is written unless all of
+    /// them fit in memory.
+    pub fn poke(&mut self, addr: u16, bytes: &[u8]) -> Result<(), DebugError> {
+        let start = addr as usize;
+        let end = start + bytes.len();
+        if end > self.chip.mem.len() {
+            return Err(DebugError::AddressOutOfRange(end - 1));
+        }
+        self.chip.mem[start..end].copy_from_slice(bytes);
+        Ok(())
+    }
+
+    pub fn set_register(&mut self, x: u8, value: u8) -> Result<(), DebugError> {
+        match self.chip.v.get_mut(x as usize) {
+            Some(v) => {
+                *v = value;
+                Ok(())
+            }
+            None => Err(DebugError::InvalidRegister(x)),
+        }
+    }
+
+    pub fn set_i(&mut self, i: u16) {
+        self.chip.i = i;
+    }
+
+    /// Moves execution to `addr`. This doesn't stop the program waiting for
+    /// a key press.
+    pub fn set_pc(&mut self, addr: u16) -> Result<(), DebugError> {
+        if addr as usize >= self.chip.mem.len() {
+            return Err(DebugError::AddressOutOfRange(addr as usize));
+        }
+        self.chip.pc = addr as usize;
+        Ok(())
+    }
+
+    pub fn set_delay(&mut self, delay: u8) {
+        self.chip.delay = delay;
+    }
+
+    pub fn set_sound(&mut self, sound: u8) {
+        self.chip.sound = sound;
+    }
+}
+
+impl Chip8 {
+    /// The registers, to be printed.
+    pub fn registers(&self) -> Registers<'_> {
+        Registers(self)
+    }
+
+    /// Access to change the machine behind the program's back. For debugging
+    /// only.
+    pub fn debug(&mut self) -> DebugAccess<'_> {
+        DebugAccess { chip: self }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers() {
+        let mut chip = Chip8::new(vec![0x61, 0x07, 0x22, 0x00]);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(
+            chip.registers().to_string(),
+            "V0=00 V1=07 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00\n\
+             V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00\n\
+             I=0000 PC=0200 SP=1 DT=00 ST=00\n"
+        );
+    }
+
+    #[test]
+    fn mutators() {
+        let mut chip = Chip8::new(vec![0x00, 0xE0]);
+        let mut debug = chip.debug();
+        debug.poke(0x300, &[0x60, 0x2A]).unwrap();
+        debug.set_pc(0x300).unwrap();
+        debug.set_register(0xF, 1).unwrap();
+        debug.set_i(0x123);
+        assert_eq!(
+            debug.poke(0xFFF, &[1, 2]),
+            Err(DebugError::AddressOutOfRange(0x1000))
+        );
+        assert_eq!(
+            debug.set_pc(0x1000),
+            Err(DebugError::AddressOutOfRange(0x1000))
+        );
+        assert_eq!(
+            debug.set_register(16, 0),
+            Err(DebugError::InvalidRegister(16))
+        );
+        chip.step().unwrap();
+        assert_eq!(chip.v()[0], 0x2A);
+        assert_eq!(chip.v()[0xF], 1);
+        assert_eq!(chip.i(), 0x123);
+        assert_eq!(chip.memory()[0xFFF], 0);
+    }
+}
@@ -0,0 +1,374 @@
+//! The instruction set of CHIP-8 and its SUPER-CHIP and XO-CHIP extensions,
+//! shared by the interpreter and the tools.
+
+use crate::Platform;
+use std::error::Error;
+use std::fmt;
+
+/// A decoded instruction. `x` and `y` are register numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// `0NNN`: call a machine code routine.
+    Sys(u16),
+    /// `00E0`
+    Cls,
+    /// `00EE`
+    Ret,
+    /// `00CN`
+    ScrollDown(u8),
+    /// `00DN`
+    ScrollUp(u8),
+    /// `00FB`
+    ScrollRight,
+    /// `00FC`
+    ScrollLeft,
+    /// `00FD`
+    Exit,
+    /// `00FE`
+    Low,
+    /// `00FF`
+    High,
+    /// `1NNN`
+    Jp(u16),
+    /// `2NNN`
+    Call(u16),
+    /// `3XNN`
+    SeByte { x: u8, byte: u8 },
+    /// `4XNN`
+    SneByte { x: u8, byte: u8 },
+    /// `5XY0`
+    SeReg { x: u8, y: u8 },
+    /// `5XY2`
+    SaveRange { x: u8, y: u8 },
+    /// `5XY3`
+    LoadRange { x: u8, y: u8 },
+    /// `6XNN`
+    LdByte { x: u8, byte: u8 },
+    /// `7XNN`
+    AddByte { x: u8, byte: u8 },
+    /// `8XY0`
+    Ld { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    Add { x: u8, y: u8 },
+    /// `8XY5`
+    Sub { x: u8, y: u8 },
+    /// `8XY6`
+    Shr { x: u8, y: u8 },
+    /// `8XY7`
+    Subn { x: u8, y: u8 },
+    /// `8XYE`
+    Shl { x: u8, y: u8 },
+    /// `9XY0`
+    SneReg { x: u8, y: u8 },
+    /// `ANNN`
+    LdI(u16),
+    /// `BNNN`
+    JpV0(u16),
+    /// `CXNN`
+    Rnd { x: u8, byte: u8 },
+    /// `DXYN`
+    Drw { x: u8, y: u8, n: u8 },
+    /// `EX9E`
+    Skp(u8),
+    /// `EXA1`
+    Sknp(u8),
+    /// `F000 NNNN`: the address is stored in the word after the instruction.
+    LdILong,
+    /// `FN01`
+    Plane(u8),
+    /// `F002`
+    Audio,
+    /// `FX07`
+    LdVxDt(u8),
+    /// `FX0A`
+    LdVxK(u8),
+    /// `FX15`
+    LdDtVx(u8),
+    /// `FX18`
+    LdStVx(u8),
+    /// `FX1E`
+    AddI(u8),
+    /// `FX29`
+    LdF(u8),
+    /// `FX30`
+    LdHf(u8),
+    /// `FX33`
+    LdB(u8),
+    /// `FX3A`
+    Pitch(u8),
+    /// `FX55`
+    LdIVx(u8),
+    /// `FX65`
+    LdVxI(u8),
+    /// `FX75`
+    LdRVx(u8),
+    /// `FX85`
+    LdVxR(u8),
+}
+
+/// Raised when a word isn't an instruction of any of the supported platforms.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError {
+    pub opcode: u16,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "unknown opcode {:04X}", self.opcode)
+    }
+}
+
+impl Error for DecodeError {}
+
+/// Decodes `opcode`. Instructions are decoded whatever the platform, see
+/// `Instruction::supported_by`.
+pub fn decode(opcode: u16) -> Result<Instruction, DecodeError> {
+    use Instruction::*;
+    let x = ((opcode & 0x0F00) >> 8) as u8;
+    let y = ((opcode & 0x00F0) >> 4) as u8;
+    let n = (opcode & 0x000F) as u8;
+    let byte = opcode as u8;
+    let addr = opcode & 0x0FFF;
+    let instruction = match opcode & 0xF000 {
+        0x0000 => match opcode {
+            0x00E0 => Cls,
+            0x00EE => Ret,
+            0x00C0..=0x00CF => ScrollDown(n),
+            0x00D0..=0x00DF => ScrollUp(n),
+            0x00FB => ScrollRight,
+            0x00FC => ScrollLeft,
+            0x00FD => Exit,
+            0x00FE => Low,
+            0x00FF => High,
+            _ => Sys(addr),
+        },
+        0x1000 => Jp(addr),
+        0x2000 => Call(addr),
+        0x3000 => SeByte { x, byte },
+        0x4000 => SneByte { x, byte },
+        0x5000 => match n {
+            0x0 => SeReg { x, y },
+            0x2 => SaveRange { x, y },
+            0x3 => LoadRange { x, y },
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x6000 => LdByte { x, byte },
+        0x7000 => AddByte { x, byte },
+        0x8000 => match n {
+            0x0 => Ld { x, y },
+            0x1 => Or { x, y },
+            0x2 => And { x, y },
+            0x3 => Xor { x, y },
+            0x4 => Add { x, y },
+            0x5 => Sub { x, y },
+            0x6 => Shr { x, y },
+            0x7 => Subn { x, y },
+            0xE => Shl { x, y },
+            _ => return Err(DecodeError { opcode }),
+        },
+        0x9000 if n == 0 => SneReg { x, y },
+        0xA000 => LdI(addr),
+        0xB000 => JpV0(addr),
+        0xC000 => Rnd { x, byte },
+        0xD000 => Drw { x, y, n },
+        0xE000 => match byte {
+            0x9E => Skp(x),
+            0xA1 => Sknp(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        0xF000 => match byte {
+            0x00 if x == 0 => LdILong,
+            0x01 => Plane(x),
+            0x02 if x == 0 => Audio,
+            0x07 => LdVxDt(x),
+            0x0A => LdVxK(x),
+            0x15 => LdDtVx(x),
+            0x18 => LdStVx(x),
+            0x1E => AddI(x),
+            0x29 => LdF(x),
+            0x30 => LdHf(x),
+            0x33 => LdB(x),
+            0x3A => Pitch(x),
+            0x55 => LdIVx(x),
+            0x65 => LdVxI(x),
+            0x75 => LdRVx(x),
+            0x85 => LdVxR(x),
+            _ => return Err(DecodeError { opcode }),
+        },
+        _ => return Err(DecodeError { opcode }),
+    };
+    Ok(instruction)
+}
+
+/// Encodes `instruction`. Register numbers and operands are truncated to the
+/// width of their field.
+pub fn encode(instruction: Instruction) -> u16 {
+    use Instruction::*;
+    let xy =
+        |op: u16, x: u8, y: u8, n: u16| op | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | n;
+    let xnn = |op: u16, x: u8, byte: u8| op | ((x as u16 & 0xF) << 8) | byte as u16;
+    let fx = |x: u8, byte: u16| 0xF000 | ((x as u16 & 0xF) << 8) | byte;
+    match instruction {
+        Sys(addr) => addr & 0x0FFF,
+        Cls => 0x00E0,
+        Ret => 0x00EE,
+        ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+        ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+        ScrollRight => 0x00FB,
+        ScrollLeft => 0x00FC,
+        Exit => 0x00FD,
+        Low => 0x00FE,
+        High => 0x00FF,
+        Jp(addr) => 0x1000 | (addr & 0x0FFF),
+        Call(addr) => 0x2000 | (addr & 0x0FFF),
+        SeByte { x, byte } => xnn(0x3000, x, byte),
+        SneByte { x, byte } => xnn(0x4000, x, byte),
+        SeReg { x, y } => xy(0x5000, x, y, 0x0),
+        SaveRange { x, y } => xy(0x5000, x, y, 0x2),
+        LoadRange { x, y } => xy(0x5000, x, y, 0x3),
+        LdByte { x, byte } => xnn(0x6000, x, byte),
+        AddByte { x, byte } => xnn(0x7000, x, byte),
+        Ld { x, y } => xy(0x8000, x, y, 0x0),
+        Or { x, y } => xy(0x8000, x, y, 0x1),
+        And { x, y } => xy(0x8000, x, y, 0x2),
+        Xor { x, y } => xy(0x8000, x, y, 0x3),
+        Add { x, y } => xy(0x8000, x, y, 0x4),
+        Sub { x, y } => xy(0x8000, x, y, 0x5),
+        Shr { x, y } => xy(0x8000, x, y, 0x6),
+        Subn { x, y } => xy(0x8000, x, y, 0x7),
+        Shl { x, y } => xy(0x8000, x, y, 0xE),
+        SneReg { x, y } => xy(0x9000, x, y, 0x0),
+        LdI(addr) => 0xA000 | (addr & 0x0FFF),
+        JpV0(addr) => 0xB000 | (addr & 0x0FFF),
+        Rnd { x, byte } => xnn(0xC000, x, byte),
+        Drw { x, y, n } => xy(0xD000, x, y, n as u16 & 0xF),
+        Skp(x) => xnn(0xE000, x, 0x9E),
+        Sknp(x) => xnn(0xE000, x, 0xA1),
+        LdILong => 0xF000,
+        Plane(n) => fx(n, 0x01),
+        Audio => 0xF002,
+        LdVxDt(x) => fx(x, 0x07),
+        LdVxK(x) => fx(x, 0x0A),
+        LdDtVx(x) => fx(x, 0x15),
+        LdStVx(x) => fx(x, 0x18),
+        AddI(x) => fx(x, 0x1E),
+        LdF(x) => fx(x, 0x29),
+        LdHf(x) => fx(x, 0x30),
+        LdB(x) => fx(x, 0x33),
+        Pitch(x) => fx(x, 0x3A),
+        LdIVx(x) => fx(x, 0x55),
+        LdVxI(x) => fx(x, 0x65),
+        LdRVx(x) => fx(x, 0x75),
+        LdVxR(x) => fx(x, 0x85),
+    }
+}
+
+impl Instruction {
+    /// Whether the interpreter runs this instruction on `platform`. `0NNN`
+    /// machine code routines are never supported.
+    pub fn supported_by(&self, platform: Platform) -> bool {
+        use Instruction::*;
+        let extended = platform != Platform::Chip8;
+        let xo = platform == Platform::XoChip;
+        match *self {
+            Sys(_) => false,
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Low | High | LdHf(_) => extended,
+            // SUPER-CHIP only has 8 RPL user flags, XO-CHIP has 16
+            LdRVx(x) | LdVxR(x) => xo || (extended && x < 8),
+            ScrollUp(_)
+            | SaveRange { .. }
+            | LoadRange { .. }
+            | LdILong
+            | Plane(_)
+            | Audio
+            | Pitch(_) => xo,
+            _ => true,
+        }
+    }
+
+    /// The address this instruction jumps to or calls, if any. For `BNNN`
+    /// this is the base address.
+    pub fn target(&self) -> Option<u16> {
+        match *self {
+            Instruction::Jp(addr) | Instruction::Call(addr) | Instruction::JpV0(addr) => Some(addr),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes() {
+        assert_eq!(decode(0x00E0), Ok(Instruction::Cls));
+        assert_eq!(decode(0x00C4), Ok(Instruction::ScrollDown(4)));
+        assert_eq!(decode(0x0123), Ok(Instruction::Sys(0x123)));
+        assert_eq!(decode(0x1ABC), Ok(Instruction::Jp(0xABC)));
+        assert_eq!(decode(0x8AB6), Ok(Instruction::Shr { x: 0xA, y: 0xB }));
+        assert_eq!(decode(0xD125), Ok(Instruction::Drw { x: 1, y: 2, n: 5 }));
+        assert_eq!(decode(0xF000), Ok(Instruction::LdILong));
+        assert_eq!(decode(0xF301), Ok(Instruction::Plane(3)));
+        assert_eq!(decode(0xF165), Ok(Instruction::LdVxI(1)));
+    }
+
+    #[test]
+    fn rejects_unknown() {
+        for opcode in [0x5001, 0x8008, 0x9001, 0xE000, 0xF100, 0xF0FF].iter() {
+            assert_eq!(decode(*opcode), Err(DecodeError { opcode: *opcode }));
+        }
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut decoded = 0;
+        for opcode in 0..=u16::MAX {
+            if let Ok(instruction) = decode(opcode) {
+                assert_eq!(encode(instruction), opcode, "{:?}", instruction);
+                decoded += 1;
+            }
+        }
+        // 11 whole groups, 13 variants of 5XY_, 8XY_ and 9XY_, 2 of EX__,
+        // 14 of FX__, and F000 and F002
+        assert_eq!(decoded, 11 * 0x1000 + 13 * 0x100 + 2 * 0x10 + 14 * 0x10 + 2);
+    }
+
+    #[test]
+    fn encode_decode() {
+        let instructions = [
+            Instruction::Ret,
+            Instruction::Call(0x345),
+            Instruction::Rnd { x: 3, byte: 0x0F },
+            Instruction::Drw {
+                x: 0xE,
+                y: 0xF,
+                n: 0,
+            },
+            Instruction::LoadRange { x: 5, y: 2 },
+            Instruction::Audio,
+            Instruction::LdVxR(0xC),
+        ];
+        for instruction in instructions.iter() {
+            assert_eq!(decode(encode(*instruction)), Ok(*instruction));
+        }
+    }
+
+    #[test]
+    fn platforms() {
+        assert!(Instruction::Cls.supported_by(Platform::Chip8));
+        assert!(!Instruction::High.supported_by(Platform::Chip8));
+        assert!(Instruction::High.supported_by(Platform::SuperChip));
+        assert!(!Instruction::Audio.supported_by(Platform::SuperChip));
+        assert!(Instruction::LdRVx(7).supported_by(Platform::SuperChip));
+        assert!(!Instruction::LdRVx(8).supported_by(Platform::SuperChip));
+        assert!(Instruction::LdRVx(8).supported_by(Platform::XoChip));
+        assert!(!Instruction::Sys(0x123).supported_by(Platform::XoChip));
+    }
+}
@@ -0,0 +1,753 @@
+//! Bindings of host keys and gamepad buttons to the CHIP-8 keypad, read from
+//! a TOML config.
+//!
+//! The `[keys]` table binds each CHIP-8 key, in hex, to a list of keyboard key
+//! names, and the `[pad]` table to a list of gamepad button names. The
+//! `[roms."<name>".keys]` and `[roms."<name>".pad]` tables do the same for one
+//! ROM, named by its file name or SHA-1 hash. Keys missing from a table keep
+//! their previous bindings, and `[]` unbinds a key:
+//!
+//! ```toml
+//! [keys]
+//! 5 = ["W", "Up"]
+//!
+//! [roms."brix.ch8".keys]
+//! 4 = ["Left"]
+//! 6 = ["Right"]
+//!
+//! [roms."brix.ch8".pad]
+//! 4 = ["DPadLeft", "West"]
+//! ```
+//!
+//! Names are those of the frontend's keys and buttons and compared without
+//! case, and unknown ones are rejected. The frontend's hotkeys,
+//! `RESERVED_KEYS`, can't be bound.
+
+use std::error::Error;
+use std::fmt;
+use toml::Value;
+
+/// The host keys of the COSMAC VIP keypad, laid out on the left of a QWERTY
+/// keyboard:
+///
+/// ```text
+/// 1 2 3 C    1 2 3 4
+/// 4 5 6 D    Q W E R
+/// 7 8 9 E    A S D F
+/// A 0 B F    Z X C V
+/// ```
+const DEFAULT_KEYS: [&str; 16] = [
+    "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+];
+
+/// The keyboard keys the frontend uses itself: quitting, muting, saving and
+/// loading states, screenshots, fullscreen and rewinding.
+pub const RESERVED_KEYS: [&str; 17] = [
+    "Escape",
+    "M",
+    "F1",
+    "F2",
+    "F3",
+    "F4",
+    "F5",
+    "F6",
+    "F7",
+    "F8",
+    "F9",
+    "F10",
+    "F11",
+    "F12",
+    "Backspace",
+    "LShift",
+    "RShift",
+];
+
+/// Gamepad buttons for the keys most games move and fire with: 2, 4, 6 and 8
+/// are up, left, right and down, and 5 is the action button.
+const DEFAULT_BUTTONS: [(u8, &[&str]); 5] = [
+    (0x2, &["DPadUp", "LeftStickUp"]),
+    (0x4, &["DPadLeft", "LeftStickLeft"]),
+    (0x5, &["South"]),
+    (0x6, &["DPadRight", "LeftStickRight"]),
+    (0x8, &["DPadDown", "LeftStickDown"]),
+];
+
+/// The inputs bound to keys by `Keymap::bind_hints`, by the names of the
+/// chip-8-database: keyboard keys, then gamepad buttons.
+const HINTS: [(&str, &[&str], &[&str]); 6] = [
+    ("up", &["Up"], &["DPadUp", "LeftStickUp"]),
+    ("down", &["Down"], &["DPadDown", "LeftStickDown"]),
+    ("left", &["Left"], &["DPadLeft", "LeftStickLeft"]),
+    ("right", &["Right"], &["DPadRight", "LeftStickRight"]),
+    ("a", &["Space"], &["South"]),
+    ("b", &["LCtrl"], &["East"]),
+];
+
+/// The names of Piston's keyboard keys, as in its `Key` enum, apart from the
+/// ones `Device::has_input` matches by pattern: letters, digits, `F1` to
+/// `F24`, `NumPad0` to `NumPad9` and `NumPadA` to `NumPadF`.
+const KEY_NAMES: [&str; 159] = [
+    "Backspace",
+    "Tab",
+    "Return",
+    "Escape",
+    "Space",
+    "Exclaim",
+    "Quotedbl",
+    "Hash",
+    "Dollar",
+    "Percent",
+    "Ampersand",
+    "Quote",
+    "LeftParen",
+    "RightParen",
+    "Asterisk",
+    "Plus",
+    "Comma",
+    "Minus",
+    "Period",
+    "Slash",
+    "Colon",
+    "Semicolon",
+    "Less",
+    "Equals",
+    "Greater",
+    "Question",
+    "At",
+    "LeftBracket",
+    "Backslash",
+    "RightBracket",
+    "Caret",
+    "Underscore",
+    "Backquote",
+    "Delete",
+    "CapsLock",
+    "PrintScreen",
+    "ScrollLock",
+    "Pause",
+    "Insert",
+    "Home",
+    "PageUp",
+    "End",
+    "PageDown",
+    "Right",
+    "Left",
+    "Down",
+    "Up",
+    "NumLockClear",
+    "NumPadDivide",
+    "NumPadMultiply",
+    "NumPadMinus",
+    "NumPadPlus",
+    "NumPadEnter",
+    "NumPadPeriod",
+    "Application",
+    "Power",
+    "NumPadEquals",
+    "Execute",
+    "Help",
+    "Menu",
+    "Select",
+    "Stop",
+    "Again",
+    "Undo",
+    "Cut",
+    "Copy",
+    "Paste",
+    "Find",
+    "Mute",
+    "VolumeUp",
+    "VolumeDown",
+    "NumPadComma",
+    "NumPadEqualsAS400",
+    "AltErase",
+    "Sysreq",
+    "Cancel",
+    "Clear",
+    "Prior",
+    "Return2",
+    "Separator",
+    "Out",
+    "Oper",
+    "ClearAgain",
+    "CrSel",
+    "ExSel",
+    "NumPad00",
+    "NumPad000",
+    "ThousandsSeparator",
+    "DecimalSeparator",
+    "CurrencyUnit",
+    "CurrencySubUnit",
+    "NumPadLeftParen",
+    "NumPadRightParen",
+    "NumPadLeftBrace",
+    "NumPadRightBrace",
+    "NumPadTab",
+    "NumPadBackspace",
+    "NumPadXor",
+    "NumPadPower",
+    "NumPadPercent",
+    "NumPadLess",
+    "NumPadGreater",
+    "NumPadAmpersand",
+    "NumPadDblAmpersand",
+    "NumPadVerticalBar",
+    "NumPadDblVerticalBar",
+    "NumPadColon",
+    "NumPadHash",
+    "NumPadSpace",
+    "NumPadAt",
+    "NumPadExclam",
+    "NumPadMemStore",
+    "NumPadMemRecall",
+    "NumPadMemClear",
+    "NumPadMemAdd",
+    "NumPadMemSubtract",
+    "NumPadMemMultiply",
+    "NumPadMemDivide",
+    "NumPadPlusMinus",
+    "NumPadClear",
+    "NumPadClearEntry",
+    "NumPadBinary",
+    "NumPadOctal",
+    "NumPadDecimal",
+    "NumPadHexadecimal",
+    "LCtrl",
+    "LShift",
+    "LAlt",
+    "LGui",
+    "RCtrl",
+    "RShift",
+    "RAlt",
+    "RGui",
+    "Mode",
+    "AudioNext",
+    "AudioPrev",
+    "AudioStop",
+    "AudioPlay",
+    "AudioMute",
+    "MediaSelect",
+    "Www",
+    "Mail",
+    "Calculator",
+    "Computer",
+    "AcSearch",
+    "AcHome",
+    "AcBack",
+    "AcForward",
+    "AcStop",
+    "AcRefresh",
+    "AcBookmarks",
+    "BrightnessDown",
+    "BrightnessUp",
+    "DisplaySwitch",
+    "KbdIllumToggle",
+    "KbdIllumDown",
+    "KbdIllumUp",
+    "Eject",
+    "Sleep",
+];
+
+/// The names of gamepad inputs: gilrs' buttons, then the directions of the
+/// sticks.
+const BUTTON_NAMES: [&str; 27] = [
+    "South",
+    "East",
+    "North",
+    "West",
+    "C",
+    "Z",
+    "LeftTrigger",
+    "LeftTrigger2",
+    "RightTrigger",
+    "RightTrigger2",
+    "Select",
+    "Start",
+    "Mode",
+    "LeftThumb",
+    "RightThumb",
+    "DPadUp",
+    "DPadDown",
+    "DPadLeft",
+    "DPadRight",
+    "LeftStickUp",
+    "LeftStickDown",
+    "LeftStickLeft",
+    "LeftStickRight",
+    "RightStickUp",
+    "RightStickDown",
+    "RightStickLeft",
+    "RightStickRight",
+];
+
+/// Where inputs come from, each has its own bindings.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Keyboard,
+    Gamepad,
+}
+
+impl Device {
+    /// The name of the device's tables in the config.
+    fn table(self) -> &'static str {
+        match self {
+            Device::Keyboard => "keys",
+            Device::Gamepad => "pad",
+        }
+    }
+
+    /// Whether the device has an input called `name`, compared without case.
+    fn has_input(self, name: &str) -> bool {
+        let names: &[&str] = match self {
+            Device::Keyboard => &KEY_NAMES,
+            Device::Gamepad => &BUTTON_NAMES,
+        };
+        if names.iter().any(|n| n.eq_ignore_ascii_case(name)) {
+            return true;
+        }
+        if self == Device::Gamepad {
+            return false;
+        }
+        let name = name.to_ascii_uppercase();
+        let digits = |n: &str| !n.starts_with('0') && n.bytes().all(|b| b.is_ascii_digit());
+        let function = name
+            .strip_prefix('F')
+            .filter(|n| digits(n))
+            .and_then(|n| n.parse::<u8>().ok());
+        let numpad = name.strip_prefix("NUMPAD");
+        match name.as_bytes() {
+            [c] => c.is_ascii_alphanumeric(),
+            _ => {
+                function.is_some_and(|n| (1..=24).contains(&n))
+                    || numpad.is_some_and(|k| k.len() == 1 && k.as_bytes()[0].is_ascii_hexdigit())
+            }
+        }
+    }
+}
+
+/// Errors raised when a config can't be read.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeymapError {
+    /// The config isn't valid TOML.
+    Toml(String),
+    /// A table or binding is invalid, the first string says where.
+    Invalid(String, String),
+}
+
+impl fmt::Display for KeymapError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeymapError::Toml(message) => write!(f, "{}", message),
+            KeymapError::Invalid(table, message) => write!(f, "in [{}]: {}", table, message),
+        }
+    }
+}
+
+impl Error for KeymapError {}
+
+/// The host keys and buttons bound to each CHIP-8 key.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    keys: [Vec<String>; 16],
+    buttons: [Vec<String>; 16],
+}
+
+impl Default for Keymap {
+    fn default() -> Keymap {
+        Keymap::new()
+    }
+}
+
+impl Keymap {
+    /// The QWERTY layout of `DEFAULT_KEYS` and the gamepad layout of
+    /// `DEFAULT_BUTTONS`.
+    pub fn new() -> Keymap {
+        let mut keymap = Keymap {
+            keys: Default::default(),
+            buttons: Default::default(),
+        };
+        for (key, &host) in DEFAULT_KEYS.iter().enumerate() {
+            keymap.keys[key].push(host.to_string());
+        }
+        for &(key, buttons) in DEFAULT_BUTTONS.iter() {
+            keymap.buttons[key as usize] = buttons.iter().map(|b| b.to_string()).collect();
+        }
+        keymap
+    }
+
+    /// Applies the `[keys]` and `[pad]` tables of `config`, then the ones of
+    /// the first ROM in `roms` that has a table.
+    pub fn apply_config(&mut self, config: &str, roms: &[&str]) -> Result<(), KeymapError> {
+        let config: Value = config
+            .parse()
+            .map_err(|e: toml::de::Error| KeymapError::Toml(e.to_string()))?;
+        let tables = config.get("roms").and_then(Value::as_table);
+        let rom = roms
+            .iter()
+            .find_map(|&name| Some((name, tables?.get(name)?)));
+        for &device in [Device::Keyboard, Device::Gamepad].iter() {
+            if let Some(table) = config.get(device.table()) {
+                self.apply_table(device, table, device.table())?;
+            }
+            if let Some((name, table)) =
+                rom.and_then(|(name, rom)| Some((name, rom.get(device.table())?)))
+            {
+                let name = format!("roms.\"{}\".{}", name, device.table());
+                self.apply_table(device, table, &name)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_table(
+        &mut self,
+        device: Device,
+        table: &Value,
+        name: &str,
+    ) -> Result<(), KeymapError> {
+        let invalid = |message: String| KeymapError::Invalid(name.to_string(), message);
+        let table = table
+            .as_table()
+            .ok_or_else(|| invalid("expected a table".to_string()))?;
+        let mut bound: Vec<String> = vec![];
+        for (key, hosts) in table {
+            let key = match u8::from_str_radix(key, 16) {
+                Ok(k) if k < 16 && key.len() == 1 => k,
+                _ => return Err(invalid(format!("invalid CHIP-8 key '{}'", key))),
+            };
+            let hosts: Vec<String> = match hosts {
+                Value::String(host) => vec![host.clone()],
+                Value::Array(hosts) => hosts
+                    .iter()
+                    .map(|host| host.as_str().map(str::to_string))
+                    .collect::<Option<_>>()
+                    .ok_or_else(|| invalid(format!("key {:X} must be bound to names", key)))?,
+                _ => return Err(invalid(format!("key {:X} must be bound to names", key))),
+            };
+            for host in hosts.iter() {
+                if !device.has_input(host) {
+                    return Err(invalid(format!("unknown name '{}'", host)));
+                }
+                let reserved = RESERVED_KEYS.iter().any(|r| r.eq_ignore_ascii_case(host));
+                if device == Device::Keyboard && reserved {
+                    return Err(invalid(format!(
+                        "'{}' is a hotkey and can't be bound",
+                        host
+                    )));
+                }
+                if bound.iter().any(|b| b.eq_ignore_ascii_case(host)) {
+                    return Err(invalid(format!("'{}' is bound twice", host)));
+                }
+                bound.push(host.clone());
+            }
+            self.bind(device, key, hosts);
+        }
+        Ok(())
+    }
+
+    fn bindings(&self, device: Device) -> &[Vec<String>; 16] {
+        match device {
+            Device::Keyboard => &self.keys,
+            Device::Gamepad => &self.buttons,
+        }
+    }
+
+    fn bindings_mut(&mut self, device: Device) -> &mut [Vec<String>; 16] {
+        match device {
+            Device::Keyboard => &mut self.keys,
+            Device::Gamepad => &mut self.buttons,
+        }
+    }
+
+    /// Binds `key` to `hosts` instead of its current inputs from `device`,
+    /// unbinding them from other keys.
+    pub fn bind(&mut self, device: Device, key: u8, hosts: Vec<String>) {
+        let bindings = self.bindings_mut(device);
+        for keys in bindings.iter_mut() {
+            keys.retain(|k| !hosts.iter().any(|host| host.eq_ignore_ascii_case(k)));
+        }
+        bindings[key as usize] = hosts;
+    }
+
+    /// Binds `host` to `key` as well as its current inputs, unbinding it
+    /// from other keys.
+    pub fn add(&mut self, device: Device, key: u8, host: &str) {
+        let bindings = self.bindings_mut(device);
+        for keys in bindings.iter_mut() {
+            keys.retain(|k| !k.eq_ignore_ascii_case(host));
+        }
+        bindings[key as usize].push(host.to_string());
+    }
+
+    /// Binds the arrow keys, the D-pad, the left stick and two buttons on
+    /// each device to the keys a ROM uses, named as in the chip-8-database:
+    /// `up`, `down`, `left`, `right`, `a` and `b`. Other names are ignored.
+    pub fn bind_hints(&mut self, hints: &[(String, u8)]) {
+        for (name, key) in hints {
+            if let Some((_, keys, buttons)) = HINTS.iter().find(|(hint, ..)| hint == name) {
+                for host in keys.iter() {
+                    self.add(Device::Keyboard, *key, host);
+                }
+                for host in buttons.iter() {
+                    self.add(Device::Gamepad, *key, host);
+                }
+            }
+        }
+    }
+
+    /// The CHIP-8 key bound to the input `host` of `device`.
+    pub fn key(&self, device: Device, host: &str) -> Option<u8> {
+        self.bindings(device)
+            .iter()
+            .position(|keys| keys.iter().any(|k| k.eq_ignore_ascii_case(host)))
+            .map(|key| key as u8)
+    }
+
+    /// The inputs of `device` bound to `key`.
+    pub fn hosts(&self, device: Device, key: u8) -> &[String] {
+        &self.bindings(device)[key as usize]
+    }
+}
+
+/// Writes the keymap as `[keys]` and `[pad]` tables.
+impl fmt::Display for Keymap {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &device) in [Device::Keyboard, Device::Gamepad].iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            writeln!(f, "[{}]", device.table())?;
+            for (key, hosts) in self.bindings(device).iter().enumerate() {
+                let hosts: Vec<String> = hosts.iter().map(|h| format!("{:?}", h)).collect();
+                writeln!(f, "{:X} = [{}]", key, hosts.join(", "))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The bound inputs held down, so that a CHIP-8 key bound to several of them
+/// is only released with the last one.
+#[derive(Debug, Clone, Default)]
+pub struct HeldInputs {
+    held: Vec<(Device, String)>,
+}
+
+impl HeldInputs {
+    pub fn new() -> HeldInputs {
+        HeldInputs::default()
+    }
+
+    fn is_down(&self, keymap: &Keymap, key: u8) -> bool {
+        self.held
+            .iter()
+            .any(|(device, host)| keymap.key(*device, host) == Some(key))
+    }
+
+    /// Returns the key to press when `host` goes down, unless it's unbound
+    /// or its key is already down.
+    pub fn press(&mut self, keymap: &Keymap, device: Device, host: &str) -> Option<u8> {
+        let key = keymap.key(device, host)?;
+        let down = self.is_down(keymap, key);
+        if !self.held.iter().any(|(d, h)| *d == device && h == host) {
+            self.held.push((device, host.to_string()));
+        }
+        if down {
+            None
+        } else {
+            Some(key)
+        }
+    }
+
+    /// Returns the key to release when `host` goes up, unless it's unbound,
+    /// wasn't down or another input holds its key down.
+    pub fn release(&mut self, keymap: &Keymap, device: Device, host: &str) -> Option<u8> {
+        let key = keymap.key(device, host)?;
+        let i = self
+            .held
+            .iter()
+            .position(|(d, h)| *d == device && h == host)?;
+        self.held.remove(i);
+        if self.is_down(keymap, key) {
+            None
+        } else {
+            Some(key)
+        }
+    }
+
+    /// Lets go of every input of `device`, e.g. when its release events will
+    /// be lost, and returns the keys to release.
+    pub fn release_all(&mut self, keymap: &Keymap, device: Device) -> Vec<u8> {
+        let released: Vec<String> = self
+            .held
+            .iter()
+            .filter(|(d, _)| *d == device)
+            .map(|(_, host)| host.clone())
+            .collect();
+        released
+            .iter()
+            .filter_map(|host| self.release(keymap, device, host))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults() {
+        let keymap = Keymap::new();
+        assert_eq!(keymap.key(Device::Keyboard, "x"), Some(0));
+        assert_eq!(keymap.key(Device::Keyboard, "4"), Some(0xC));
+        assert_eq!(keymap.key(Device::Keyboard, "V"), Some(0xF));
+        assert_eq!(keymap.key(Device::Keyboard, "Up"), None);
+        assert_eq!(keymap.hosts(Device::Keyboard, 5), ["W"]);
+        assert_eq!(keymap.key(Device::Gamepad, "LeftStickDown"), Some(8));
+        assert_eq!(keymap.key(Device::Gamepad, "W"), None);
+        assert_eq!(keymap.hosts(Device::Gamepad, 5), ["South"]);
+    }
+
+    #[test]
+    fn config() {
+        let config = r#"
+            [keys]
+            5 = ["Z", "Up"]
+            a = "Y"
+            0 = []
+
+            [pad]
+            0 = "Z"
+
+            [roms."brix.ch8".keys]
+            4 = ["Left", "Up"]
+            [roms."brix.ch8".pad]
+            4 = ["West"]
+            [roms."other.ch8".keys]
+            4 = ["B"]
+        "#;
+        let mut keymap = Keymap::new();
+        keymap.apply_config(config, &["0a1b", "brix.ch8"]).unwrap();
+        let keys = |key| keymap.hosts(Device::Keyboard, key);
+        assert_eq!(keys(5), ["Z"]);
+        assert_eq!(keys(4), ["Left", "Up"]);
+        assert_eq!(keys(0), [] as [String; 0]);
+        assert_eq!(keymap.key(Device::Keyboard, "y"), Some(0xA));
+        assert_eq!(keymap.key(Device::Keyboard, "Q"), None);
+        assert_eq!(keymap.key(Device::Keyboard, "X"), None);
+        assert_eq!(keymap.hosts(Device::Gamepad, 4), ["West"]);
+        assert_eq!(keymap.key(Device::Gamepad, "Z"), Some(0));
+        assert_eq!(keymap.key(Device::Gamepad, "DPadLeft"), None);
+        assert_eq!(keymap.key(Device::Gamepad, "DPadUp"), Some(2));
+
+        let mut other = Keymap::new();
+        other.apply_config(&keymap.to_string(), &[]).unwrap();
+        assert_eq!(other, keymap);
+    }
+
+    #[test]
+    fn errors() {
+        let invalid = |config: &str| match Keymap::new().apply_config(config, &["rom"]) {
+            Err(KeymapError::Invalid(table, _)) => table,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(invalid("keys = 1"), "keys");
+        assert_eq!(invalid("[keys]\n10 = \"A\""), "keys");
+        assert_eq!(invalid("[keys]\n1 = [2]"), "keys");
+        assert_eq!(invalid("[keys]\n1 = \"A\"\n2 = [\"a\"]"), "keys");
+        assert_eq!(invalid("[roms.rom.keys]\nG = \"A\""), "roms.\"rom\".keys");
+        assert_eq!(invalid("[roms.rom]\npad = []"), "roms.\"rom\".pad");
+        assert_eq!(invalid("[keys]\n1 = \"f1\""), "keys");
+        assert_eq!(
+            invalid("[roms.rom.keys]\n1 = [\"A\", \"Backspace\"]"),
+            "roms.\"rom\".keys"
+        );
+        assert_eq!(invalid("[keys]\n1 = \"Uo\""), "keys");
+        assert_eq!(invalid("[keys]\n1 = \"Space \""), "keys");
+        assert_eq!(invalid("[keys]\n1 = \"F25\""), "keys");
+        assert_eq!(invalid("[pad]\n1 = \"Space\""), "pad");
+        assert!(matches!(
+            Keymap::new().apply_config("[keys", &[]),
+            Err(KeymapError::Toml(_))
+        ));
+    }
+
+    #[test]
+    fn names() {
+        let keys = [
+            "a",
+            "7",
+            "F1",
+            "f12",
+            "NumPad5",
+            "NumPadA",
+            "NumPadEnter",
+            "Comma",
+            "LCtrl",
+        ];
+        assert!(keys.iter().all(|key| Device::Keyboard.has_input(key)));
+        let unknown = ["", "AA", "F0", "F01", "F+5", "NumPadG", "NumPad10", "South"];
+        assert!(!unknown.iter().any(|key| Device::Keyboard.has_input(key)));
+        assert!(Device::Gamepad.has_input("leftstickup"));
+        assert!(!Device::Gamepad.has_input("A"));
+        // the defaults and hints only use known names
+        let keymap = Keymap::new();
+        for &device in [Device::Keyboard, Device::Gamepad].iter() {
+            let hosts = keymap.bindings(device).iter().flatten();
+            assert!(hosts.clone().all(|host| device.has_input(host)));
+        }
+        for (_, keys, buttons) in HINTS.iter() {
+            assert!(keys.iter().all(|key| Device::Keyboard.has_input(key)));
+            assert!(buttons.iter().all(|b| Device::Gamepad.has_input(b)));
+        }
+    }
+
+    #[test]
+    fn held() {
+        let mut keymap = Keymap::new();
+        keymap.bind(Device::Keyboard, 5, vec!["W".to_string(), "Up".to_string()]);
+        let mut held = HeldInputs::new();
+        assert_eq!(held.press(&keymap, Device::Keyboard, "W"), Some(5));
+        assert_eq!(held.press(&keymap, Device::Keyboard, "W"), None);
+        assert_eq!(held.press(&keymap, Device::Keyboard, "Up"), None);
+        assert_eq!(held.press(&keymap, Device::Gamepad, "South"), None);
+        assert_eq!(held.release(&keymap, Device::Keyboard, "W"), None);
+        assert_eq!(held.release(&keymap, Device::Gamepad, "South"), None);
+        assert_eq!(held.release(&keymap, Device::Keyboard, "Up"), Some(5));
+        assert_eq!(held.release(&keymap, Device::Keyboard, "Up"), None);
+        assert_eq!(held.press(&keymap, Device::Keyboard, "M"), None);
+        assert_eq!(held.press(&keymap, Device::Gamepad, "DPadUp"), Some(2));
+    }
+
+    #[test]
+    fn release_all() {
+        let mut keymap = Keymap::new();
+        keymap.bind(Device::Keyboard, 5, vec!["W".to_string(), "Up".to_string()]);
+        let mut held = HeldInputs::new();
+        held.press(&keymap, Device::Keyboard, "W");
+        held.press(&keymap, Device::Keyboard, "Up");
+        held.press(&keymap, Device::Keyboard, "1");
+        held.press(&keymap, Device::Gamepad, "DPadUp");
+        held.press(&keymap, Device::Keyboard, "2");
+        assert_eq!(held.release_all(&keymap, Device::Keyboard), [5, 1]);
+        assert!(held.release_all(&keymap, Device::Keyboard).is_empty());
+        assert_eq!(held.release(&keymap, Device::Gamepad, "DPadUp"), Some(2));
+    }
+
+    #[test]
+    fn hints() {
+        let mut keymap = Keymap::new();
+        let hints = [("up", 5), ("a", 6), ("player2Up", 1)];
+        let hints: Vec<(String, u8)> = hints.iter().map(|&(n, k)| (n.to_string(), k)).collect();
+        keymap.bind_hints(&hints);
+        assert_eq!(keymap.hosts(Device::Keyboard, 5), ["W", "Up"]);
+        assert_eq!(keymap.hosts(Device::Keyboard, 6), ["E", "Space"]);
+        assert_eq!(keymap.hosts(Device::Gamepad, 5), ["DPadUp", "LeftStickUp"]);
+        assert_eq!(
+            keymap.hosts(Device::Gamepad, 6),
+            ["DPadRight", "LeftStickRight", "South"]
+        );
+        assert_eq!(keymap.hosts(Device::Gamepad, 2), [] as [String; 0]);
+        assert_eq!(keymap.hosts(Device::Keyboard, 1), ["1"]);
+    }
+}
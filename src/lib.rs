@@ -1,4 +1,55 @@
-use rand::random;
+use rng::Rng;
+use sha1::Sha1;
+
+pub mod asm;
+pub mod audio;
+pub mod database;
+pub mod debugger;
+pub mod disasm;
+mod display;
+mod error;
+pub mod export;
+pub mod headless;
+mod inspect;
+mod instruction;
+pub mod keymap;
+pub mod movie;
+mod platform;
+mod quirks;
+pub mod rewind;
+mod rng;
+pub mod state;
+pub mod trace;
+pub mod viewport;
+
+pub use display::{Screen, PLANES};
+pub use error::{Chip8Error, LoadError, StepOutcome};
+pub use inspect::{DebugAccess, DebugError, Registers};
+pub use instruction::{decode, encode, DecodeError, Instruction};
+pub use platform::Platform;
+pub use quirks::Quirks;
+pub use state::StateError;
+pub use trace::Tracer;
+
+/// Rate of the delay and sound timers, and of the display refresh.
+pub const TIMER_HZ: u32 = 60;
+
+/// Returns how many instructions to run per frame to emulate a CPU clocked at
+/// `cpu_hz`.
+pub fn cycles_per_frame(cpu_hz: u32) -> usize {
+    (cpu_hz.saturating_add(TIMER_HZ / 2) / TIMER_HZ).max(1) as usize
+}
+
+/// What the interpreter does when a program accesses memory past the end of
+/// the address space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum MemoryPolicy {
+    /// Stop with `Chip8Error::MemoryOutOfBounds`.
+    #[default]
+    Error,
+    /// Wrap the address around to the start of memory.
+    Wrap,
+}
 
 const BUILTIN_SPRITES: [u8; 80] = [
     0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
@@ -19,57 +70,210 @@ const BUILTIN_SPRITES: [u8; 80] = [
     0xF0, 0x80, 0xF0, 0x80, 0x80, // F
 ];
 
+/// Address of the 8x10 SUPER-CHIP font, stored right after the small font.
+const BIG_SPRITES_ADDR: usize = 0x50;
+
+const BIG_SPRITES: [u8; 160] = [
+    0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+    0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+    0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+    0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+    0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+    0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+    0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+    0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+    0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+    0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+    0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+];
+
 pub struct Chip8 {
-    mem: [u8; 0xFFF],
+    platform: Platform,
+    rom_hash: [u8; 20],
+    quirks: Quirks,
+    mem: Vec<u8>,
     v: [u8; 16],
     i: u16,
     pc: usize,
-    screen: [u64; 32],
+    screen: Screen,
     delay: u8,
     sound: u8,
     sp: usize,
     stack: [u16; 16],
     keyboard: u16,
     halted: Option<u8>,
+    waiting_vblank: bool,
+    exited: bool,
+    rpl: [u8; 16],
+    audio_pattern: [u8; 16],
+    pitch: u8,
+    memory_policy: MemoryPolicy,
+    rng: Rng,
+    tracer: Option<Box<Tracer>>,
 }
 
-#[inline]
-const fn r1(opcode: u16) -> usize {
-    ((opcode & 0x0F00) >> 8) as usize
-}
-
-#[inline]
-const fn r2(opcode: u16) -> usize {
-    ((opcode & 0x00F0) >> 4) as usize
-}
-
-#[inline]
-const fn cst(opcode: u16) -> u8 {
-    opcode as u8
+/// The registers VX to VY used by `5XY2`/`5XY3`, in descending order if X > Y.
+fn register_range(x: u8, y: u8) -> Box<dyn Iterator<Item = usize>> {
+    let (x, y) = (x as usize, y as usize);
+    if x <= y {
+        Box::new(x..=y)
+    } else {
+        Box::new((y..=x).rev())
+    }
 }
 
 impl Chip8 {
+    /// Creates a machine running `prg` on the default platform.
+    ///
+    /// Panics if the program does not fit in memory, use `Chip8::from_rom` to
+    /// handle that case.
     pub fn new(prg: Vec<u8>) -> Chip8 {
-        let mut mem = [0; 0xFFF];
-        for (i, b) in prg.iter().enumerate() {
-            mem[0x200 + i] = *b;
-        }
-        for i in 0..80 {
-            mem[i] = BUILTIN_SPRITES[i];
+        Chip8::from_rom(&prg).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    /// Creates a machine running `rom` on the default platform.
+    pub fn from_rom(rom: &[u8]) -> Result<Chip8, LoadError> {
+        Chip8::with_platform(Platform::default(), rom)
+    }
+
+    /// Creates a machine running `rom` on the given platform.
+    pub fn with_platform(platform: Platform, rom: &[u8]) -> Result<Chip8, LoadError> {
+        if rom.len() > platform.max_rom_size() {
+            return Err(LoadError::RomTooLarge {
+                size: rom.len(),
+                max: platform.max_rom_size(),
+            });
         }
-        Chip8 {
+        let mut mem = vec![0; platform.memory_size()];
+        let start = platform.program_start();
+        mem[start..start + rom.len()].copy_from_slice(rom);
+        mem[..BUILTIN_SPRITES.len()].copy_from_slice(&BUILTIN_SPRITES);
+        mem[BIG_SPRITES_ADDR..BIG_SPRITES_ADDR + BIG_SPRITES.len()].copy_from_slice(&BIG_SPRITES);
+        Ok(Chip8 {
+            platform,
+            rom_hash: Sha1::from(rom).digest().bytes(),
+            quirks: platform.default_quirks(),
             mem,
             v: [0; 16],
             i: 0,
-            pc: 0x200,
-            screen: [0; 32],
+            pc: start,
+            screen: Screen::default(),
             delay: 0,
             sound: 0,
             sp: 0,
             stack: [0; 16],
             keyboard: 0,
             halted: None,
+            waiting_vblank: false,
+            exited: false,
+            rpl: [0; 16],
+            audio_pattern: [0; 16],
+            pitch: 64,
+            memory_policy: MemoryPolicy::default(),
+            rng: Rng::new(rand::random()),
+            tracer: None,
+        })
+    }
+
+    pub fn platform(&self) -> Platform {
+        self.platform
+    }
+
+    /// The SHA-1 hash of the ROM the machine was created with.
+    pub fn rom_hash(&self) -> &[u8; 20] {
+        &self.rom_hash
+    }
+
+    /// Parses a ROM hash written in hexadecimal, as in movies and ROM
+    /// databases.
+    pub(crate) fn parse_rom_hash(hex: &str) -> Option<[u8; 20]> {
+        if hex.len() != 40 || !hex.is_ascii() {
+            return None;
+        }
+        let mut hash = [0; 20];
+        for (i, b) in hash.iter_mut().enumerate() {
+            *b = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()?;
         }
+        Some(hash)
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        self.memory_policy
+    }
+
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.memory_policy = policy;
+    }
+
+    /// Makes `CXNN` draw the same numbers every time for a given seed. The
+    /// machine starts with a random seed.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Traces every instruction run from now on, see the `trace` module.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Stops tracing and returns the tracer.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take().map(|tracer| *tracer)
+    }
+
+    /// The address of the next instruction.
+    pub fn pc(&self) -> u16 {
+        self.pc as u16
+    }
+
+    pub fn v(&self) -> &[u8; 16] {
+        &self.v
+    }
+
+    pub fn i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn sp(&self) -> usize {
+        self.sp
+    }
+
+    /// The return addresses of the subroutines being run, innermost last.
+    pub fn stack(&self) -> &[u16] {
+        &self.stack[..self.sp]
+    }
+
+    pub fn delay(&self) -> u8 {
+        self.delay
+    }
+
+    /// The whole address space, including the fonts below the program.
+    pub fn memory(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// The register `FX0A` stores the next key press in, while the program
+    /// waits for one.
+    pub fn waiting_for_key(&self) -> Option<u8> {
+        self.halted
+    }
+
+    /// Whether the program ended with `00FD`.
+    pub fn has_exited(&self) -> bool {
+        self.exited
     }
 
     pub fn decrement_delay(&mut self) {
@@ -78,6 +282,34 @@ impl Chip8 {
         }
     }
 
+    /// Runs up to `cycles` instructions followed by one timer tick, which
+    /// emulates one 60 Hz frame.
+    ///
+    /// The frame ends early when the interpreter blocks on a key press or the
+    /// display wait quirk, as nothing more can happen until the next frame.
+    /// If the program exits or fails, the timers are not ticked.
+    pub fn run_frame(&mut self, cycles: usize) -> Result<StepOutcome, Chip8Error> {
+        let mut outcome = StepOutcome::Executed;
+        for _ in 0..cycles {
+            outcome = self.step()?;
+            match outcome {
+                StepOutcome::Executed => {}
+                StepOutcome::Exited => return Ok(outcome),
+                StepOutcome::WaitingForKey | StepOutcome::WaitingForVBlank => break,
+            }
+        }
+        self.tick_timers();
+        Ok(outcome)
+    }
+
+    /// Advances the 60 Hz timers by one tick. This is also the vertical blank
+    /// that `DXYN` waits for when `Quirks::display_wait` is set.
+    pub fn tick_timers(&mut self) {
+        self.decrement_delay();
+        self.decrement_sound();
+        self.waiting_vblank = false;
+    }
+
     pub fn sound(&self) -> u8 {
         self.sound
     }
@@ -88,7 +320,23 @@ impl Chip8 {
         }
     }
 
-    pub fn screen(&self) -> &[u64; 32] {
+    /// The XO-CHIP audio pattern: 128 one bit samples played while the sound
+    /// timer is active.
+    pub fn audio_pattern(&self) -> &[u8; 16] {
+        &self.audio_pattern
+    }
+
+    /// The XO-CHIP playback rate of the audio pattern, see `playback_rate`.
+    pub fn pitch(&self) -> u8 {
+        self.pitch
+    }
+
+    /// The rate in Hz at which the audio pattern bits are played back.
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+
+    pub fn screen(&self) -> &Screen {
         &self.screen
     }
 
@@ -109,174 +357,348 @@ impl Chip8 {
         (self.keyboard & (0x1 << key)) != 0
     }
 
-    pub fn step(&mut self) {
+    /// Executes the instruction at the program counter.
+    ///
+    /// On error the machine is left exactly as it was before the call, so the
+    /// caller can report the problem and decide whether to keep going.
+    pub fn step(&mut self) -> Result<StepOutcome, Chip8Error> {
+        if self.exited {
+            return Ok(StepOutcome::Exited);
+        }
         if self.halted.is_some() {
-            return;
+            return Ok(StepOutcome::WaitingForKey);
+        }
+        if self.waiting_vblank {
+            return Ok(StepOutcome::WaitingForVBlank);
         }
-        let opcode: u16 = ((self.mem[self.pc] as u16) << 8) + self.mem[self.pc + 1] as u16;
-        match opcode & 0xF000 {
-            0x0000 => match opcode & 0x00FF {
-                0x00E0 => self.screen = [0; 32],
-                0x00EE => {
-                    self.sp -= 1;
-                    self.pc = self.stack[self.sp] as usize;
+        let opcode: u16 = ((self.read(self.pc)? as u16) << 8) + self.read(self.pc + 1)? as u16;
+        if let Some(mut tracer) = self.tracer.take() {
+            tracer.record(self, self.resolve(self.pc)?, opcode);
+            self.tracer = Some(tracer);
+        }
+        let invalid = Chip8Error::InvalidOpcode {
+            addr: self.pc as u16,
+            opcode,
+        };
+        let instruction = match decode(opcode) {
+            Ok(instruction) if instruction.supported_by(self.platform) => instruction,
+            _ => return Err(invalid),
+        };
+        match instruction {
+            Instruction::Cls => self.screen.clear(),
+            Instruction::Ret => {
+                if self.sp == 0 {
+                    return Err(Chip8Error::StackUnderflow {
+                        addr: self.pc as u16,
+                    });
                 }
-                _ => unreachable!("No such opcode: {:X}", opcode),
-            },
-            0x1000 => {
-                self.pc = (opcode & 0x0FFF) as usize;
-                return;
+                self.sp -= 1;
+                self.pc = self.stack[self.sp] as usize;
+            }
+            Instruction::ScrollDown(n) => self.screen.scroll_down(n as usize),
+            Instruction::ScrollUp(n) => self.screen.scroll_up(n as usize),
+            Instruction::ScrollRight => self.screen.scroll_right(4),
+            Instruction::ScrollLeft => self.screen.scroll_left(4),
+            Instruction::Exit => {
+                self.exited = true;
+                return Ok(StepOutcome::Exited);
             }
-            0x2000 => {
+            Instruction::Low => self.screen.set_hires(false),
+            Instruction::High => self.screen.set_hires(true),
+            Instruction::Jp(addr) => {
+                self.pc = addr as usize;
+                return Ok(StepOutcome::Executed);
+            }
+            Instruction::Call(addr) => {
+                if self.sp == self.stack.len() {
+                    return Err(Chip8Error::StackOverflow {
+                        addr: self.pc as u16,
+                    });
+                }
                 self.stack[self.sp] = self.pc as u16;
                 self.sp += 1;
-                self.pc = (opcode & 0x0FFF) as usize;
-                return;
+                self.pc = addr as usize;
+                return Ok(StepOutcome::Executed);
             }
-            0x3000 => {
-                if self.v[r1(opcode)] == cst(opcode) {
-                    self.pc += 2;
+            Instruction::SeByte { x, byte } => {
+                if self.v[x as usize] == byte {
+                    self.skip();
                 }
             }
-            0x4000 => {
-                if self.v[r1(opcode)] != cst(opcode) {
-                    self.pc += 2;
+            Instruction::SneByte { x, byte } => {
+                if self.v[x as usize] != byte {
+                    self.skip();
                 }
             }
-            0x5000 => {
-                if self.v[r1(opcode)] == self.v[r2(opcode)] {
-                    self.pc += 2;
+            Instruction::SeReg { x, y } => {
+                if self.v[x as usize] == self.v[y as usize] {
+                    self.skip();
                 }
             }
-            0x6000 => self.v[r1(opcode)] = cst(opcode),
-            0x7000 => self.v[r1(opcode)] = self.v[r1(opcode)].wrapping_add(cst(opcode)),
-            0x8000 => match opcode & 0x000F {
-                0x0000 => self.v[r1(opcode)] = self.v[r2(opcode)],
-                0x0001 => self.v[r1(opcode)] |= self.v[r2(opcode)],
-                0x0002 => self.v[r1(opcode)] &= self.v[r2(opcode)],
-                0x0003 => self.v[r1(opcode)] ^= self.v[r2(opcode)],
-                0x0004 => {
-                    let (res, carry) = self.v[r1(opcode)].overflowing_add(self.v[r2(opcode)]);
-                    self.v[0xF] = if carry { 1 } else { 0 };
-                    self.v[r1(opcode)] = res;
-                }
-                0x0005 => {
-                    let (res, borrow) = self.v[r1(opcode)].overflowing_sub(self.v[r2(opcode)]);
-                    self.v[0xF] = if borrow { 0 } else { 1 };
-                    self.v[r1(opcode)] = res;
-                }
-                0x0006 => {
-                    self.v[0xF] = self.v[r2(opcode)] & 0x01;
-                    self.v[r1(opcode)] = self.v[r2(opcode)].overflowing_shr(1).0;
-                }
-                0x0007 => {
-                    let (res, borrow) = self.v[r2(opcode)].overflowing_sub(self.v[r1(opcode)]);
-                    self.v[0xF] = if borrow { 0 } else { 1 };
-                    self.v[r1(opcode)] = res;
-                }
-                0x000E => {
-                    self.v[0xF] = (self.v[r2(opcode)] & 0b10000000) >> 7;
-                    self.v[r1(opcode)] = self.v[r2(opcode)].overflowing_shl(1).0;
+            Instruction::SaveRange { x, y } => {
+                let values: Vec<u8> = register_range(x, y).map(|r| self.v[r]).collect();
+                self.write_range(self.i as usize, &values)?;
+            }
+            Instruction::LoadRange { x, y } => {
+                let values = self.read_range(self.i as usize, register_range(x, y).count())?;
+                for (r, value) in register_range(x, y).zip(values) {
+                    self.v[r] = value;
                 }
-                _ => unreachable!("No such opcode: {:X}", opcode),
-            },
-            0x9000 => {
-                if self.v[r1(opcode)] != self.v[r2(opcode)] {
-                    self.pc += 2;
+            }
+            Instruction::LdByte { x, byte } => self.v[x as usize] = byte,
+            Instruction::AddByte { x, byte } => {
+                self.v[x as usize] = self.v[x as usize].wrapping_add(byte)
+            }
+            Instruction::Ld { x, y } => self.v[x as usize] = self.v[y as usize],
+            Instruction::Or { x, y } => {
+                self.v[x as usize] |= self.v[y as usize];
+                self.reset_vf();
+            }
+            Instruction::And { x, y } => {
+                self.v[x as usize] &= self.v[y as usize];
+                self.reset_vf();
+            }
+            Instruction::Xor { x, y } => {
+                self.v[x as usize] ^= self.v[y as usize];
+                self.reset_vf();
+            }
+            // VF is written last so that it holds the flag even when X is F
+            Instruction::Add { x, y } => {
+                let (res, carry) = self.v[x as usize].overflowing_add(self.v[y as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = carry as u8;
+            }
+            Instruction::Sub { x, y } => {
+                let (res, borrow) = self.v[x as usize].overflowing_sub(self.v[y as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = !borrow as u8;
+            }
+            Instruction::Shr { x, y } => {
+                let src = self.v[self.shift_source(x, y)];
+                self.v[x as usize] = src >> 1;
+                self.v[0xF] = src & 0x01;
+            }
+            Instruction::Subn { x, y } => {
+                let (res, borrow) = self.v[y as usize].overflowing_sub(self.v[x as usize]);
+                self.v[x as usize] = res;
+                self.v[0xF] = !borrow as u8;
+            }
+            Instruction::Shl { x, y } => {
+                let src = self.v[self.shift_source(x, y)];
+                self.v[x as usize] = src << 1;
+                self.v[0xF] = src >> 7;
+            }
+            Instruction::SneReg { x, y } => {
+                if self.v[x as usize] != self.v[y as usize] {
+                    self.skip();
                 }
             }
-            0xA000 => self.i = opcode & 0x0FFF,
-            0xB000 => {
-                self.pc = ((opcode & 0x0FFF) + self.v[0] as u16) as usize;
-                return;
+            Instruction::LdI(addr) => self.i = addr,
+            Instruction::JpV0(addr) => {
+                // with the jump quirk, BXNN jumps to XNN + VX
+                let offset = if self.quirks.jump {
+                    self.v[(addr >> 8) as usize]
+                } else {
+                    self.v[0]
+                };
+                self.pc = (addr + offset as u16) as usize;
+                return Ok(StepOutcome::Executed);
             }
-            0xC000 => {
-                self.v[r1(opcode)] = random::<u8>() & cst(opcode);
+            Instruction::Rnd { x, byte } => {
+                self.v[x as usize] = self.rng.next_u8() & byte;
             }
-            0xD000 => {
+            Instruction::Drw { x, y, n } => {
                 let start: usize = self.i as usize;
-                let n: usize = (opcode & 0x000F) as usize;
-                let x = self.v[r1(opcode)];
-                let mut y = self.v[r2(opcode)];
-                y &= 31;
-                for b in start..(n + start) {
-                    self.draw_byte(self.mem[b], x as usize, y as usize);
-                    y = (y + 1) & 31;
+                let n = n as usize;
+                // DXY0 draws a 16x16 sprite
+                let (rows, width) = if n == 0 && self.extended() {
+                    (16, 16)
+                } else {
+                    (n, 8)
+                };
+                // each selected plane gets its own copy of the sprite data
+                let planes: Vec<usize> = (0..PLANES)
+                    .filter(|plane| self.screen.selected_planes() & (1 << plane) != 0)
+                    .collect();
+                let len = rows * width / 8;
+                let sprite = self.read_range(start, len * planes.len())?;
+                let x = self.v[x as usize] as usize % self.screen.width();
+                let y = self.v[y as usize] as usize % self.screen.height();
+                let mut collision = false;
+                for (plane, data) in planes.into_iter().zip(sprite.chunks(len.max(1))) {
+                    for (row, bytes) in data.chunks(width / 8).enumerate() {
+                        let bits = bytes.iter().fold(0, |acc, b| (acc << 8) | *b as u16);
+                        collision |= self.draw_row(plane, bits, width, x, y + row);
+                    }
                 }
+                self.v[0xF] = collision as u8;
+                self.waiting_vblank = self.quirks.display_wait;
             }
-            0xE000 => match opcode & 0x00FF {
-                0x009E => {
-                    if self.keyboard & ((0x1 << self.v[r1(opcode)]) as u16) != 0 {
-                        self.pc += 2;
-                    }
+            Instruction::Skp(x) => {
+                if self.is_pressed(self.v[x as usize] & 0xF) {
+                    self.skip();
                 }
-                0x00A1 => {
-                    if self.keyboard & ((0x1 << self.v[r1(opcode)]) as u16) == 0 {
-                        self.pc += 2;
-                    }
+            }
+            Instruction::Sknp(x) => {
+                if !self.is_pressed(self.v[x as usize] & 0xF) {
+                    self.skip();
                 }
-                _ => unreachable!("No such opcode: {:X}", opcode),
-            },
-            0xF000 => {
-                match opcode & 0x00FF {
-                    0x0007 => self.v[r1(opcode)] = self.delay,
-                    // handled in key_press
-                    0x000A => {
-                        self.halted = Some(r1(opcode) as u8);
-                        return;
-                    }
-                    0x0015 => self.delay = self.v[r1(opcode)],
-                    0x0018 => self.sound = self.v[r1(opcode)],
-                    0x001E => {
-                        if self.i + self.v[r1(opcode)] as u16 > 0xFFF {
-                            self.v[0xF] = 1;
-                        } else {
-                            self.v[0xF] = 0;
-                        }
-                        self.i += self.v[r1(opcode)] as u16;
-                    },
-                    0x0029 => self.i = 5 * self.v[r1(opcode)] as u16,
-                    0x0033 => {
-                        let vx = self.v[r1(opcode)];
-                        self.mem[self.i as usize] = vx as u8 / 100;
-                        self.mem[self.i as usize + 1] = (vx as u8 / 10) % 10;
-                        self.mem[self.i as usize + 2] = vx as u8 % 10;
-                    }
-                    0x0055 => {
-                        for i in 0..=r1(opcode) {
-                            self.mem[self.i as usize + i as usize] = self.v[i as usize];
-                        }
-                        self.i += r1(opcode) as u16 + 1;
-                    }
-                    0x0065 => {
-                        for i in 0..=r1(opcode) {
-                            self.v[i as usize] = self.mem[self.i as usize + i as usize];
-                        }
-                        self.i += r1(opcode) as u16 + 1
-                    }
-                    _ => unreachable!("No such opcode: {:X}", opcode),
+            }
+            // F000 NNNN loads a 16 bit address into I
+            Instruction::LdILong => {
+                let hi = self.read(self.pc + 2)?;
+                let lo = self.read(self.pc + 3)?;
+                self.i = ((hi as u16) << 8) | lo as u16;
+                self.pc += 4;
+                return Ok(StepOutcome::Executed);
+            }
+            Instruction::Plane(n) => self.screen.select_planes(n),
+            Instruction::Audio => {
+                let pattern = self.read_range(self.i as usize, 16)?;
+                self.audio_pattern.copy_from_slice(&pattern);
+            }
+            Instruction::LdVxDt(x) => self.v[x as usize] = self.delay,
+            // handled in key_press
+            Instruction::LdVxK(x) => {
+                self.halted = Some(x);
+                return Ok(StepOutcome::WaitingForKey);
+            }
+            Instruction::LdDtVx(x) => self.delay = self.v[x as usize],
+            Instruction::LdStVx(x) => self.sound = self.v[x as usize],
+            Instruction::AddI(x) => {
+                let i = self.i.wrapping_add(self.v[x as usize] as u16);
+                self.v[0xF] = (i as usize >= self.mem.len()) as u8;
+                self.i = i;
+            }
+            Instruction::LdF(x) => self.i = 5 * self.v[x as usize] as u16,
+            Instruction::LdHf(x) => {
+                let digit = (self.v[x as usize] & 0xF) as usize;
+                self.i = (BIG_SPRITES_ADDR + 10 * digit) as u16;
+            }
+            Instruction::Pitch(x) => self.pitch = self.v[x as usize],
+            Instruction::LdB(x) => {
+                let vx = self.v[x as usize];
+                self.write_range(self.i as usize, &[vx / 100, (vx / 10) % 10, vx % 10])?;
+            }
+            Instruction::LdIVx(x) => {
+                let regs = self.v;
+                self.write_range(self.i as usize, &regs[..=x as usize])?;
+                if self.quirks.load_store {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            Instruction::LdVxI(x) => {
+                let values = self.read_range(self.i as usize, x as usize + 1)?;
+                self.v[..values.len()].copy_from_slice(&values);
+                if self.quirks.load_store {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
                 }
             }
-            _ => unreachable!("No such opcode: {:X}", opcode),
+            Instruction::LdRVx(x) => {
+                self.rpl[..=x as usize].copy_from_slice(&self.v[..=x as usize]);
+            }
+            Instruction::LdVxR(x) => {
+                self.v[..=x as usize].copy_from_slice(&self.rpl[..=x as usize]);
+            }
+            Instruction::Sys(_) => return Err(invalid),
         }
         self.pc += 2;
+        Ok(StepOutcome::Executed)
+    }
+
+    /// Whether the SUPER-CHIP instructions are available.
+    fn extended(&self) -> bool {
+        self.platform != Platform::Chip8
     }
 
-    fn draw_byte(&mut self, byte: u8, x: usize, y: usize) {
-        let mut to_store = 1;
-        for i in 0..8 {
-            let bit_to_write = (byte >> (7 - i) & 0x1) as u64;
-            to_store ^= (!self.set_pixel(bit_to_write, (x + i) & 63, y)) as u8;
+    /// Whether the XO-CHIP instructions are available.
+    fn xo(&self) -> bool {
+        self.platform == Platform::XoChip
+    }
+
+    /// Skips the next instruction, which in XO-CHIP may be the 4 byte long
+    /// `F000 NNNN`.
+    fn skip(&mut self) {
+        let next = self.pc + 2;
+        let long = self.xo()
+            && self.read(next).ok() == Some(0xF0)
+            && self.read(next + 1).ok() == Some(0x00);
+        self.pc += if long { 4 } else { 2 };
+    }
+
+    /// The register shifted by `8XY6`/`8XYE`.
+    fn shift_source(&self, x: u8, y: u8) -> usize {
+        if self.quirks.shift {
+            x as usize
+        } else {
+            y as usize
+        }
+    }
+
+    fn reset_vf(&mut self) {
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
         }
-        self.v[0xF] = !(to_store != 0) as u8;
     }
 
-    fn set_pixel(&mut self, bit: u64, x: usize, y: usize) -> bool {
-        let mask = 1 << (63 - x);
-        let old_val = self.screen[y] & mask;
-        self.screen[y] ^= bit << (63 - x);
-        let new_val = self.screen[y] & mask;
-        old_val > 0 && new_val == 0
+    /// Maps `target` onto the address space according to the memory policy.
+    fn resolve(&self, target: usize) -> Result<usize, Chip8Error> {
+        if target < self.mem.len() {
+            return Ok(target);
+        }
+        match self.memory_policy {
+            MemoryPolicy::Error => Err(Chip8Error::MemoryOutOfBounds {
+                addr: self.pc as u16,
+                target,
+            }),
+            MemoryPolicy::Wrap => Ok(target % self.mem.len()),
+        }
+    }
+
+    fn read(&self, target: usize) -> Result<u8, Chip8Error> {
+        Ok(self.mem[self.resolve(target)?])
+    }
+
+    fn read_range(&self, start: usize, len: usize) -> Result<Vec<u8>, Chip8Error> {
+        (start..start + len)
+            .map(|target| self.read(target))
+            .collect()
+    }
+
+    /// Writes `bytes` starting at `start`. Nothing is written unless the whole
+    /// range is accessible.
+    fn write_range(&mut self, start: usize, bytes: &[u8]) -> Result<(), Chip8Error> {
+        if let Some(last) = bytes.len().checked_sub(1) {
+            self.resolve(start + last)?;
+        }
+        for (offset, b) in bytes.iter().enumerate() {
+            let target = self.resolve(start + offset)?;
+            self.mem[target] = *b;
+        }
+        Ok(())
+    }
+
+    /// Draws the `width` least significant bits of `bits` as one row of a
+    /// sprite, returning whether any pixel was erased.
+    fn draw_row(&mut self, plane: usize, bits: u16, width: usize, x: usize, y: usize) -> bool {
+        let (screen_width, screen_height) = (self.screen.width(), self.screen.height());
+        if self.quirks.clipping && y >= screen_height {
+            return false;
+        }
+        let mut collision = false;
+        for i in 0..width {
+            if self.quirks.clipping && x + i >= screen_width {
+                break;
+            }
+            let bit_to_write = (bits >> (width - 1 - i) & 0x1) as u128;
+            collision |= self.screen.set_pixel(
+                plane,
+                bit_to_write,
+                (x + i) % screen_width,
+                y % screen_height,
+            );
+        }
+        collision
     }
 }
 
@@ -289,11 +711,11 @@ mod tests {
         let prog = vec![0x00, 0xE0];
         let mut chip = Chip8::new(prog);
         for i in 0..32 {
-            chip.screen[i] = 0xFFFFFFFFFFFFFFFF;
+            chip.screen.set_row(i, 0xFFFFFFFFFFFFFFFF);
         }
-        chip.step();
+        chip.step().unwrap();
         for i in 0..32 {
-            assert_eq!(chip.screen[i], 0x0);
+            assert_eq!(chip.screen.row(i), 0x0);
         }
     }
 
@@ -303,7 +725,7 @@ mod tests {
         let mut chip = Chip8::new(prog);
         chip.sp = 1;
         chip.stack[1] = 0xAB;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.sp, 0);
         assert_eq!(chip.pc, 2);
     }
@@ -312,7 +734,7 @@ mod tests {
     fn jp() {
         let prog = vec![0x1A, 0xBC];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0xABC);
     }
 
@@ -320,7 +742,7 @@ mod tests {
     fn call() {
         let prog = vec![0x2A, 0xBC];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.sp, 1);
         assert_eq!(chip.stack[0], 0x200);
         assert_eq!(chip.pc, 0xABC);
@@ -330,9 +752,9 @@ mod tests {
     fn se() {
         let prog = vec![0x30, 0x11, 0x31, 0x00];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -340,9 +762,9 @@ mod tests {
     fn sne() {
         let prog = vec![0x40, 0x00, 0x41, 0x11];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -351,9 +773,9 @@ mod tests {
         let prog = vec![0x50, 0x10, 0x51, 0x20];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -361,7 +783,7 @@ mod tests {
     fn ld() {
         let prog = vec![0x6E, 0x12];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x12);
     }
 
@@ -370,7 +792,7 @@ mod tests {
         let prog = vec![0x7E, 0x12];
         let mut chip = Chip8::new(prog);
         chip.v[0xE] = 5;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x17);
     }
 
@@ -379,7 +801,7 @@ mod tests {
         let prog = vec![0x8E, 0x10];
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 5;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x5);
     }
 
@@ -389,7 +811,7 @@ mod tests {
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0xF;
         chip.v[0xE] = 0xF;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0xF);
     }
 
@@ -398,7 +820,7 @@ mod tests {
         let prog = vec![0x8E, 0x12];
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0xF;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x0);
     }
 
@@ -408,34 +830,50 @@ mod tests {
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0xF;
         chip.v[0xE] = 0xF;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x0);
     }
 
     #[test]
     fn add_overflow() {
-        let prog = vec![0x8E, 0x14, 0x8E, 0x14];
+        let prog = asm::assemble("ADD VE, V1\nADD VE, V1").unwrap();
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0xFE;
         chip.v[0xE] = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0xFF);
         assert_eq!(chip.v[0xF], 0x0);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0xFD);
         assert_eq!(chip.v[0xF], 0x1);
     }
 
+    #[test]
+    fn rnd() {
+        let prog = asm::assemble("RND V0, 0xFF\nRND V1, 0x0F\nRND V2, 0xFF").unwrap();
+        let run = |seed| {
+            let mut chip = Chip8::new(prog.clone());
+            chip.set_seed(seed);
+            for _ in 0..3 {
+                chip.step().unwrap();
+            }
+            chip.v
+        };
+        assert_eq!(run(5), run(5));
+        assert_ne!(run(5), run(6));
+        assert_eq!(run(5)[1] & 0xF0, 0);
+    }
+
     #[test]
     fn sub_overflow() {
         let prog = vec![0x8E, 0x15, 0x8E, 0x15];
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0x1;
         chip.v[0xE] = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x0);
         assert_eq!(chip.v[0xF], 0x1);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0xFF);
         assert_eq!(chip.v[0xF], 0x0);
     }
@@ -445,11 +883,11 @@ mod tests {
         let prog = vec![0x8E, 0x06, 0x8E, 0xE6];
         let mut chip = Chip8::new(prog);
         chip.v[0x0] = 0x02;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x01);
         assert_eq!(chip.v[0x0], 0x02);
         assert_eq!(chip.v[0xF], 0x00);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x00);
         assert_eq!(chip.v[0xF], 0x01);
     }
@@ -460,10 +898,10 @@ mod tests {
         let mut chip = Chip8::new(prog);
         chip.v[0x1] = 0x1;
         chip.v[0xE] = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0x1], 0x0);
         assert_eq!(chip.v[0xE], 0x1);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x1);
         assert_eq!(chip.v[0x1], 0x1);
     }
@@ -473,10 +911,10 @@ mod tests {
         let prog = vec![0x8E, 0x0E, 0x8E, 0xEE];
         let mut chip = Chip8::new(prog);
         chip.v[0x0] = 0x40;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x80);
         assert_eq!(chip.v[0xF], 0x0);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0xE], 0x00);
         assert_eq!(chip.v[0xF], 0x01);
     }
@@ -486,9 +924,9 @@ mod tests {
         let prog = vec![0x90, 0x00, 0x90, 0x10];
         let mut chip = Chip8::new(prog);
         chip.v[0x0] = 0xFF;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -496,7 +934,7 @@ mod tests {
     fn ld_i() {
         let prog = vec![0xAA, 0xBC];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.i, 0x0ABC);
     }
 
@@ -505,7 +943,7 @@ mod tests {
         let prog = vec![0xBA, 0xBC];
         let mut chip = Chip8::new(prog);
         chip.v[0x0] = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x0ABD);
     }
 
@@ -515,28 +953,26 @@ mod tests {
         for j in 0..32 {
             for i in 0..64 {
                 if i % 2 == 0 {
-                    assert_eq!(chip.set_pixel(1, i, j), false);
+                    assert!(!chip.screen.set_pixel(0, 1, i, j));
                 }
             }
-            assert_eq!(chip.screen[j], 0xAAAAAAAAAAAAAAAA);
+            assert_eq!(chip.screen.row(j), 0xAAAAAAAAAAAAAAAA);
         }
-        assert_eq!(chip.set_pixel(0, 0, 0), false);
-        assert_eq!(chip.set_pixel(1, 62, 0), true);
-        assert_eq!(chip.screen[0], 0xAAAAAAAAAAAAAAA8);
+        assert!(!chip.screen.set_pixel(0, 0, 0, 0));
+        assert!(chip.screen.set_pixel(0, 1, 62, 0));
+        assert_eq!(chip.screen.row(0), 0xAAAAAAAAAAAAAAA8);
     }
 
     #[test]
-    fn draw_byte() {
+    fn draw_row() {
         let mut chip = Chip8::new(vec![]);
         for i in 0..32 {
-            chip.draw_byte(0xEE, 60, i);
-            chip.draw_byte(0xFF, 4, i);
-            assert_eq!(chip.screen[i], 0xEFF000000000000E);
-            assert_eq!(chip.v[0xF], 0);
+            assert!(!chip.draw_row(0, 0xEE, 8, 60, i));
+            assert!(!chip.draw_row(0, 0xFF, 8, 4, i));
+            assert_eq!(chip.screen.row(i), 0xEFF000000000000E);
         }
-        chip.draw_byte(0xFF, 4, 0);
-        assert_eq!(chip.screen[0], 0xE00000000000000E);
-        assert_eq!(chip.v[0xF], 1);
+        assert!(chip.draw_row(0, 0xFF, 8, 4, 0));
+        assert_eq!(chip.screen.row(0), 0xE00000000000000E);
     }
 
     #[test]
@@ -548,12 +984,12 @@ mod tests {
         chip.i = 0x0204;
         chip.v[0x0] = 60;
         chip.v[0x1] = 31;
-        chip.step();
-        assert_eq!(chip.screen[0], 0xF00000000000000F);
-        assert_eq!(chip.screen[31], 0xF00000000000000F);
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(0), 0xF00000000000000F);
+        assert_eq!(chip.screen.row(31), 0xF00000000000000F);
         for i in 0..32 {
             if i != 0 && i != 31 {
-                assert_eq!(chip.screen[i], 0x0000000000000000);
+                assert_eq!(chip.screen.row(i), 0x0000000000000000);
             }
         }
     }
@@ -562,10 +998,10 @@ mod tests {
     fn skp() {
         let prog = vec![0xE0, 0x9E, 0xE0, 0x9E];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
         chip.keyboard = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -574,10 +1010,10 @@ mod tests {
         let prog = vec![0xE0, 0xA1, 0xE0, 0xA1];
         let mut chip = Chip8::new(prog);
         chip.keyboard = 0x1;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x202);
         chip.keyboard = 0x0;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x206);
     }
 
@@ -586,7 +1022,7 @@ mod tests {
         let prog = vec![0xF0, 0x07];
         let mut chip = Chip8::new(prog);
         chip.delay = 0xA;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.v[0x0], 0xA);
     }
 
@@ -594,10 +1030,10 @@ mod tests {
     fn ldk() {
         let prog = vec![0xF0, 0x0A];
         let mut chip = Chip8::new(prog);
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x200);
-        chip.step();
-        chip.step();
+        chip.step().unwrap();
+        chip.step().unwrap();
         assert_eq!(chip.pc, 0x200);
         chip.press_key(1);
         assert_eq!(chip.pc, 0x202);
@@ -609,7 +1045,7 @@ mod tests {
         let prog = vec![0xF0, 0x15];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 0xA;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.delay, 0xA);
     }
 
@@ -618,7 +1054,7 @@ mod tests {
         let prog = vec![0xF0, 0x18];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 0xA;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.sound, 0xA);
     }
 
@@ -627,7 +1063,7 @@ mod tests {
         let prog = vec![0xF0, 0x1E];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 0xA;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.i, 0xA);
     }
 
@@ -636,7 +1072,7 @@ mod tests {
         let prog = vec![0xF0, 0x29];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 0xA;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.i, 0x32);
     }
 
@@ -645,7 +1081,7 @@ mod tests {
         let prog = vec![0xF0, 0x33];
         let mut chip = Chip8::new(prog);
         chip.v[0] = 123;
-        chip.step();
+        chip.step().unwrap();
         assert_eq!(chip.mem[chip.i as usize], 0x1);
         assert_eq!(chip.mem[(chip.i + 1) as usize], 0x2);
         assert_eq!(chip.mem[(chip.i + 2) as usize], 0x3);
@@ -655,12 +1091,10 @@ mod tests {
     fn ld_store_regs() {
         let prog = vec![0xF4, 0x55];
         let mut chip = Chip8::new(prog);
-        let values = vec![0x1, 0x2, 0x3, 0x4];
-        for i in 0..4 {
-            chip.v[i] = values[i];
-        }
+        let values = [0x1, 0x2, 0x3, 0x4];
+        chip.v[..4].copy_from_slice(&values);
         chip.i = 0x300;
-        chip.step();
+        chip.step().unwrap();
         for i in 0..4 {
             assert_eq!(chip.mem[(0x300 + i) as usize], values[i as usize]);
         }
@@ -671,16 +1105,12 @@ mod tests {
     fn ld_retrieve_regs() {
         let prog = vec![0xF5, 0x65];
         let mut chip = Chip8::new(prog);
-        let values = vec![0x1, 0x2, 0x3, 0x4];
+        let values = [0x1, 0x2, 0x3, 0x4];
         chip.v[0x5] = 3;
         chip.i = 0x300;
-        for i in 0..4 {
-            chip.mem[(chip.i + i) as usize] = values[i as usize];
-        }
-        chip.step();
-        for i in 0..4 {
-            assert_eq!(chip.v[i], values[i]);
-        }
+        chip.mem[0x300..0x304].copy_from_slice(&values);
+        chip.step().unwrap();
+        assert_eq!(chip.v[..4], values);
     }
 
     #[test]
@@ -697,4 +1127,417 @@ mod tests {
         chip.press_key(10);
         assert_eq!(chip.keyboard, 0b0000010000000010)
     }
+
+    #[test]
+    fn invalid_opcode() {
+        let prog = vec![0x6E, 0x12, 0xE0, 0x00];
+        let mut chip = Chip8::new(prog);
+        chip.step().unwrap();
+        assert_eq!(
+            chip.step(),
+            Err(Chip8Error::InvalidOpcode {
+                addr: 0x202,
+                opcode: 0xE000
+            })
+        );
+        assert_eq!(chip.pc, 0x202);
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let prog = vec![0x00, 0xEE];
+        let mut chip = Chip8::new(prog);
+        assert_eq!(chip.step(), Err(Chip8Error::StackUnderflow { addr: 0x200 }));
+        assert_eq!(chip.sp, 0);
+    }
+
+    #[test]
+    fn stack_overflow() {
+        let prog = vec![0x22, 0x00];
+        let mut chip = Chip8::new(prog);
+        for _ in 0..16 {
+            chip.step().unwrap();
+        }
+        assert_eq!(chip.step(), Err(Chip8Error::StackOverflow { addr: 0x200 }));
+        assert_eq!(chip.sp, 16);
+    }
+
+    #[test]
+    fn waiting_for_key() {
+        let prog = vec![0xF0, 0x0A];
+        let mut chip = Chip8::new(prog);
+        assert_eq!(chip.step(), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(chip.step(), Ok(StepOutcome::WaitingForKey));
+    }
+
+    #[test]
+    fn drw_collision() {
+        let prog = vec![0xD0, 0x02];
+        let mut chip = Chip8::new(prog);
+        chip.mem[0x300] = 0xFF;
+        chip.mem[0x301] = 0x0F;
+        chip.i = 0x300;
+        chip.screen.set_row(0, 0x8000000000000000);
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xF], 1);
+    }
+
+    #[test]
+    fn pc_out_of_bounds() {
+        let mut chip = Chip8::new(vec![]);
+        chip.pc = 0xFFF;
+        assert_eq!(
+            chip.step(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0xFFF,
+                target: 0x1000
+            })
+        );
+    }
+
+    #[test]
+    fn ld_store_regs_out_of_bounds() {
+        let prog = vec![0xF3, 0x55];
+        let mut chip = Chip8::new(prog);
+        chip.v[..4].copy_from_slice(&[1, 2, 3, 4]);
+        chip.i = 0xFFE;
+        assert_eq!(
+            chip.step(),
+            Err(Chip8Error::MemoryOutOfBounds {
+                addr: 0x200,
+                target: 0x1001
+            })
+        );
+        assert_eq!(chip.mem[0xFFE], 0);
+        assert_eq!(chip.i, 0xFFE);
+    }
+
+    #[test]
+    fn ld_store_regs_wrap() {
+        let prog = vec![0xF1, 0x55];
+        let mut chip = Chip8::new(prog);
+        chip.set_memory_policy(MemoryPolicy::Wrap);
+        chip.v[..2].copy_from_slice(&[0xAA, 0xBB]);
+        chip.i = 0xFFF;
+        chip.step().unwrap();
+        assert_eq!(chip.mem[0xFFF], 0xAA);
+        assert_eq!(chip.mem[0], 0xBB);
+    }
+
+    #[test]
+    fn from_rom() {
+        let chip = Chip8::from_rom(&[0xAB; 0xE00]).unwrap();
+        assert_eq!(chip.mem.len(), 0x1000);
+        assert_eq!(chip.mem[0xFFF], 0xAB);
+        assert_eq!(
+            Chip8::from_rom(&[0; 0xE01]).err(),
+            Some(LoadError::RomTooLarge {
+                size: 0xE01,
+                max: 0xE00
+            })
+        );
+    }
+
+    #[test]
+    fn shift_quirk() {
+        let prog = vec![0x8E, 0x06, 0x8E, 0x0E];
+        let mut chip = Chip8::new(prog);
+        chip.set_quirks(Quirks::chip48());
+        chip.v[0x0] = 0xFF;
+        chip.v[0xE] = 0x81;
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xE], 0x40);
+        assert_eq!(chip.v[0xF], 0x01);
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xE], 0x80);
+        assert_eq!(chip.v[0xF], 0x00);
+    }
+
+    #[test]
+    fn flag_written_last() {
+        let prog = vec![0x8F, 0x14];
+        let mut chip = Chip8::new(prog);
+        chip.v[0x1] = 0xFF;
+        chip.v[0xF] = 0x02;
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xF], 0x01);
+    }
+
+    #[test]
+    fn load_store_quirk() {
+        let prog = vec![0xF4, 0x55, 0xF4, 0x65];
+        let mut chip = Chip8::new(prog);
+        chip.set_quirks(Quirks::super_chip());
+        chip.i = 0x300;
+        chip.step().unwrap();
+        assert_eq!(chip.i, 0x300);
+        chip.step().unwrap();
+        assert_eq!(chip.i, 0x300);
+    }
+
+    #[test]
+    fn jump_quirk() {
+        let prog = vec![0xBA, 0xBC];
+        let mut chip = Chip8::new(prog);
+        chip.set_quirks(Quirks::chip48());
+        chip.v[0x0] = 0x1;
+        chip.v[0xA] = 0x2;
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x0ABE);
+    }
+
+    #[test]
+    fn vf_reset_quirk() {
+        let prog = vec![0x80, 0x11, 0x80, 0x11];
+        let mut chip = Chip8::new(prog);
+        chip.v[0xF] = 0x1;
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xF], 0x1);
+        chip.set_quirks(Quirks::cosmac_vip());
+        chip.step().unwrap();
+        assert_eq!(chip.v[0xF], 0x0);
+    }
+
+    #[test]
+    fn clipping_quirk() {
+        let prog = vec![0xD0, 0x12];
+        let mut chip = Chip8::new(prog);
+        chip.set_quirks(Quirks::cosmac_vip());
+        chip.mem[0x204] = 0xFF;
+        chip.mem[0x205] = 0xFF;
+        chip.i = 0x0204;
+        chip.v[0x0] = 60;
+        chip.v[0x1] = 31;
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(31), 0x000000000000000F);
+        assert_eq!(chip.screen.row(0), 0x0);
+    }
+
+    #[test]
+    fn display_wait_quirk() {
+        let prog = vec![0xD0, 0x01, 0x60, 0x01];
+        let mut chip = Chip8::new(prog);
+        chip.set_quirks(Quirks::cosmac_vip());
+        assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+        assert_eq!(chip.step(), Ok(StepOutcome::WaitingForVBlank));
+        assert_eq!(chip.pc, 0x202);
+        chip.tick_timers();
+        assert_eq!(chip.step(), Ok(StepOutcome::Executed));
+        assert_eq!(chip.v[0x0], 0x1);
+    }
+
+    #[test]
+    fn schip_requires_platform() {
+        let prog = vec![0x00, 0xFF];
+        let mut chip = Chip8::new(prog.clone());
+        assert_eq!(
+            chip.step(),
+            Err(Chip8Error::InvalidOpcode {
+                addr: 0x200,
+                opcode: 0x00FF
+            })
+        );
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.step().unwrap();
+        assert!(chip.screen().is_hires());
+    }
+
+    #[test]
+    fn hires_lores() {
+        let prog = vec![0x00, 0xFF, 0x00, 0xFE];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.step().unwrap();
+        assert_eq!((chip.screen.width(), chip.screen.height()), (128, 64));
+        chip.step().unwrap();
+        assert_eq!((chip.screen.width(), chip.screen.height()), (64, 32));
+    }
+
+    #[test]
+    fn scroll() {
+        let prog = vec![0x00, 0xC2, 0x00, 0xFB, 0x00, 0xFC];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.screen.set_row(0, 0x0FF0000000000000);
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(0), 0x0);
+        assert_eq!(chip.screen.row(2), 0x0FF0000000000000);
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(2), 0x00FF000000000000);
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(2), 0x0FF0000000000000);
+    }
+
+    #[test]
+    fn exit() {
+        let prog = vec![0x00, 0xFD];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        assert_eq!(chip.step(), Ok(StepOutcome::Exited));
+        assert_eq!(chip.step(), Ok(StepOutcome::Exited));
+        assert_eq!(chip.pc, 0x200);
+    }
+
+    #[test]
+    fn drw_16x16() {
+        let prog = vec![0x00, 0xFF, 0xD0, 0x10];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        for i in 0..32 {
+            chip.mem[0x300 + i] = if i % 2 == 0 { 0xFF } else { 0x01 };
+        }
+        chip.i = 0x300;
+        chip.v[0x0] = 120;
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(0), 0xFF);
+        assert_eq!(chip.screen.row(15), 0xFF);
+        assert_eq!(chip.screen.row(16), 0x0);
+        assert_eq!(chip.v[0xF], 0x0);
+    }
+
+    #[test]
+    fn ld_big_font() {
+        let prog = vec![0xF0, 0x30];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.v[0] = 0x2;
+        chip.step().unwrap();
+        assert_eq!(chip.i, 0x64);
+        assert_eq!(chip.mem[0x64..0x6E], BIG_SPRITES[20..30]);
+    }
+
+    #[test]
+    fn rpl_flags() {
+        let prog = vec![0xF2, 0x75, 0x60, 0x00, 0xF2, 0x85, 0xF8, 0x75];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.v[..3].copy_from_slice(&[1, 2, 3]);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.v[0], 0);
+        chip.step().unwrap();
+        assert_eq!(chip.v[..3], [1, 2, 3]);
+        assert!(chip.step().is_err());
+    }
+
+    #[test]
+    fn xo_memory() {
+        let chip = Chip8::with_platform(Platform::XoChip, &[0xAB; 0xFE00]).unwrap();
+        assert_eq!(chip.mem.len(), 0x10000);
+        assert_eq!(chip.mem[0xFFFF], 0xAB);
+    }
+
+    #[test]
+    fn ld_i_long() {
+        let prog = vec![0xF0, 0x00, 0xAB, 0xCD];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.i, 0xABCD);
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn skip_long() {
+        let prog = vec![0x30, 0x00, 0xF0, 0x00, 0xAB, 0xCD];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x206);
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.pc, 0x204);
+    }
+
+    #[test]
+    fn save_load_range() {
+        let prog = vec![0x51, 0x32, 0x53, 0x13, 0x52, 0x03];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        chip.v[1..4].copy_from_slice(&[1, 2, 3]);
+        chip.i = 0x300;
+        chip.step().unwrap();
+        assert_eq!(chip.mem[0x300..0x303], [1, 2, 3]);
+        assert_eq!(chip.i, 0x300);
+        chip.step().unwrap();
+        assert_eq!(chip.v[1..4], [3, 2, 1]);
+        chip.step().unwrap();
+        assert_eq!(chip.v[0..3], [3, 2, 1]);
+    }
+
+    #[test]
+    fn plane_drawing() {
+        let prog = vec![0xF3, 0x01, 0xD0, 0x01, 0xF2, 0x01, 0x00, 0xE0];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        chip.mem[0x300] = 0xF0;
+        chip.mem[0x301] = 0x0F;
+        chip.i = 0x300;
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.screen.plane_row(0, 0), 0xF000000000000000);
+        assert_eq!(chip.screen.plane_row(1, 0), 0x0F00000000000000);
+        assert_eq!(chip.screen.pixel(0, 0), 1);
+        assert_eq!(chip.screen.pixel(4, 0), 2);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.screen.plane_row(0, 0), 0xF000000000000000);
+        assert_eq!(chip.screen.plane_row(1, 0), 0x0);
+    }
+
+    #[test]
+    fn scroll_up() {
+        let prog = vec![0x00, 0xD1];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        chip.screen.set_row(1, 0xFF);
+        chip.step().unwrap();
+        assert_eq!(chip.screen.row(0), 0xFF);
+        assert_eq!(chip.screen.row(1), 0x0);
+    }
+
+    #[test]
+    fn audio() {
+        let prog = vec![0xF0, 0x02, 0xF1, 0x3A];
+        let mut chip = Chip8::with_platform(Platform::XoChip, &prog).unwrap();
+        for i in 0..16 {
+            chip.mem[0x300 + i] = i as u8;
+        }
+        chip.i = 0x300;
+        chip.v[1] = 112;
+        assert_eq!(chip.playback_rate(), 4000.0);
+        chip.step().unwrap();
+        chip.step().unwrap();
+        assert_eq!(chip.audio_pattern()[15], 15);
+        assert_eq!(chip.pitch(), 112);
+        assert_eq!(chip.playback_rate(), 8000.0);
+    }
+
+    #[test]
+    fn cycles_per_frame() {
+        assert_eq!(super::cycles_per_frame(600), 10);
+        assert_eq!(super::cycles_per_frame(700), 12);
+        assert_eq!(super::cycles_per_frame(1), 1);
+        assert_eq!(super::cycles_per_frame(u32::MAX), (u32::MAX / 60) as usize);
+    }
+
+    #[test]
+    fn run_frame() {
+        // loop: add 1 to V0 forever
+        let prog = vec![0x70, 0x01, 0x12, 0x00];
+        let mut chip = Chip8::new(prog);
+        chip.delay = 2;
+        assert_eq!(chip.run_frame(10), Ok(StepOutcome::Executed));
+        assert_eq!(chip.v[0], 5);
+        assert_eq!(chip.delay, 1);
+    }
+
+    #[test]
+    fn run_frame_stops_waiting() {
+        let prog = vec![0x70, 0x01, 0xF0, 0x0A, 0x12, 0x00];
+        let mut chip = Chip8::new(prog);
+        chip.delay = 2;
+        assert_eq!(chip.run_frame(10), Ok(StepOutcome::WaitingForKey));
+        assert_eq!(chip.v[0], 1);
+        assert_eq!(chip.delay, 1);
+    }
+
+    #[test]
+    fn run_frame_error() {
+        let prog = vec![0x70, 0x01, 0x00, 0x00];
+        let mut chip = Chip8::new(prog);
+        chip.delay = 2;
+        assert!(chip.run_frame(10).is_err());
+        assert_eq!(chip.delay, 2);
+    }
 }
@@ -1,34 +1,287 @@
-use chip8::Chip8;
+use chip8::audio::{self, AudioSink, Buzzer, NullSink, WavSink};
+use chip8::database::Database;
+use chip8::debugger::Debugger;
+use chip8::export::{self, Image, Palette};
+use chip8::keymap::{Device, HeldInputs, Keymap};
+use chip8::movie::{Movie, Playback};
+use chip8::rewind::Rewind;
+use chip8::viewport::Viewport;
+use chip8::{Chip8, MemoryPolicy, Platform, Quirks, Screen, StepOutcome, Tracer, TIMER_HZ};
 use clap::{App, Arg};
+use gilrs::{Axis, EventType, Gilrs};
 use piston_window::*;
-use std::fs::File;
-use std::io::Read;
+use rodio::buffer::SamplesBuffer;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, BufRead, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
 
-const WHITE: [f32; 4] = [1.0, 1.0, 1.0, 1.0];
-const BLACK: [f32; 4] = [0.0, 0.0, 0.0, 1.0];
+// one update per timer tick, the CPU runs several instructions per update
+const UPS: u64 = TIMER_HZ as u64;
 
-fn get_chip8_key(key: Key) -> Option<u8> {
+// how far a stick must be pushed to press a direction
+const STICK_THRESHOLD: f32 = 0.5;
+
+/// Converts a palette to the colours used by Piston.
+fn piston_colours(palette: &Palette) -> [[f32; 4]; 4] {
+    let mut colours = [[0.0; 4]; 4];
+    for (colour, rgb) in colours.iter_mut().zip(palette.iter()) {
+        for (c, &x) in colour.iter_mut().zip(rgb.iter()) {
+            *c = x as f32 / 255.0;
+        }
+        colour[3] = 1.0;
+    }
+    colours
+}
+
+/// Plays samples on the default output device.
+struct RodioSink {
+    sink: rodio::Sink,
+    sample_rate: u32,
+}
+
+impl RodioSink {
+    fn new(sample_rate: u32) -> Option<RodioSink> {
+        let device = rodio::default_output_device()?;
+        Some(RodioSink {
+            sink: rodio::Sink::new(&device),
+            sample_rate,
+        })
+    }
+}
+
+impl AudioSink for RodioSink {
+    fn write(&mut self, samples: &[f32]) -> io::Result<()> {
+        self.sink
+            .append(SamplesBuffer::new(1, self.sample_rate, samples.to_vec()));
+        Ok(())
+    }
+}
+
+/// Maps F1-F9 to the save state slots.
+fn get_state_slot(key: Key) -> Option<u8> {
     match key {
-        Key::X => Some(0),
-        Key::D1 => Some(1),
-        Key::D2 => Some(2),
-        Key::D3 => Some(3),
-        Key::Q => Some(4),
-        Key::W => Some(5),
-        Key::E => Some(6),
-        Key::A => Some(7),
-        Key::S => Some(8),
-        Key::D => Some(9),
-        Key::Z => Some(10),
-        Key::C => Some(11),
-        Key::D4 => Some(12),
-        Key::R => Some(13),
-        Key::F => Some(14),
-        Key::V => Some(15),
+        Key::F1 => Some(1),
+        Key::F2 => Some(2),
+        Key::F3 => Some(3),
+        Key::F4 => Some(4),
+        Key::F5 => Some(5),
+        Key::F6 => Some(6),
+        Key::F7 => Some(7),
+        Key::F8 => Some(8),
+        Key::F9 => Some(9),
+        _ => None,
+    }
+}
+
+fn state_path(rom: &str, slot: u8) -> String {
+    format!("{}.state{}", rom, slot)
+}
+
+fn save_state(chip: &Chip8, rom: &str, slot: u8) {
+    let path = state_path(rom, slot);
+    match fs::write(&path, chip.save_state()) {
+        Ok(()) => println!("Saved state to '{}'", path),
+        Err(e) => eprintln!("Can't save state to '{}': {}", path, e),
+    }
+}
+
+/// Parses an inclusive range of hex addresses such as `200-2FF`.
+fn parse_range(range: &str) -> Option<(u16, u16)> {
+    let mut bounds = range.splitn(2, '-');
+    let start = u16::from_str_radix(bounds.next()?.trim_start_matches("0x"), 16).ok()?;
+    let end = u16::from_str_radix(bounds.next()?.trim_start_matches("0x"), 16).ok()?;
+    Some((start, end))
+}
+
+/// Saves the screen next to the ROM, in the first free
+/// `<rom>.screenshot<N>.png`.
+fn save_screenshot(chip: &Chip8, rom: &str, scale: usize, palette: &Palette) {
+    let path = (1..)
+        .map(|n| format!("{}.screenshot{}.png", rom, n))
+        .find(|path| !Path::new(path).exists())
+        .unwrap();
+    match Image::render(chip.screen(), scale, palette).save_png(&path) {
+        Ok(()) => println!("Saved screenshot to '{}'", path),
+        Err(e) => eprintln!("Can't save screenshot to '{}': {}", path, e),
+    }
+}
+
+/// Opens the window, fullscreen or `size` pixels large.
+fn open_window(fullscreen: bool, size: [u32; 2]) -> PistonWindow {
+    let mut window: PistonWindow = WindowSettings::new("Chip8-emu", size)
+        .fullscreen(fullscreen)
+        .exit_on_esc(true)
+        .build()
+        .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
+    window.set_event_settings(EventSettings::new().ups(UPS));
+    window
+}
+
+/// Presses or releases a key, recording it in the movie being made.
+fn set_key(chip: &mut Chip8, movie: Option<&mut Movie>, frame: u64, key: u8, pressed: bool) {
+    if pressed {
+        chip.press_key(key);
+    } else {
+        chip.release_key(key);
+    }
+    if let Some(movie) = movie {
+        movie.record(frame, key, pressed);
+    }
+}
+
+/// Returns whether the state was restored.
+fn load_state(chip: &mut Chip8, rom: &str, slot: u8) -> bool {
+    let path = state_path(rom, slot);
+    let result = fs::read(&path)
+        .map_err(|e| e.to_string())
+        .and_then(|data| chip.load_state(&data).map_err(|e| e.to_string()));
+    match result {
+        Ok(()) => {
+            println!("Loaded state from '{}'", path);
+            true
+        }
+        Err(e) => {
+            eprintln!("Can't load state from '{}': {}", path, e);
+            false
+        }
+    }
+}
+
+/// Reads the debugger commands typed on the terminal without blocking the
+/// window.
+fn read_commands() -> Receiver<String> {
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let stdin = io::stdin();
+        for line in stdin.lock().lines() {
+            match line {
+                Ok(line) if tx.send(line).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+    rx
+}
+
+fn prompt() {
+    print!("(chip8) ");
+    let _ = io::stdout().flush();
+}
+
+/// The name of `key` in the keymap: its Piston name, without the `D` of
+/// digits.
+fn key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    match name.strip_prefix('D') {
+        Some(digit) if digit.len() == 1 && digit.as_bytes()[0].is_ascii_digit() => {
+            digit.to_string()
+        }
+        _ => name,
+    }
+}
+
+/// The names of the directions of an axis in the keymap, negative first.
+fn axis_directions(axis: Axis) -> Option<[&'static str; 2]> {
+    match axis {
+        Axis::LeftStickX => Some(["LeftStickLeft", "LeftStickRight"]),
+        Axis::LeftStickY => Some(["LeftStickDown", "LeftStickUp"]),
+        Axis::RightStickX => Some(["RightStickLeft", "RightStickRight"]),
+        Axis::RightStickY => Some(["RightStickDown", "RightStickUp"]),
+        // some gamepads report the D-pad as axes instead of buttons
+        Axis::DPadX => Some(["DPadLeft", "DPadRight"]),
+        Axis::DPadY => Some(["DPadDown", "DPadUp"]),
         _ => None,
     }
 }
 
+/// Turns a gamepad event into inputs going down or up, named as in the
+/// keymap.
+fn pad_inputs(event: EventType) -> Vec<(String, bool)> {
+    match event {
+        EventType::ButtonPressed(button, _) => vec![(format!("{:?}", button), true)],
+        EventType::ButtonReleased(button, _) => vec![(format!("{:?}", button), false)],
+        EventType::AxisChanged(axis, value, _) => match axis_directions(axis) {
+            Some([negative, positive]) => vec![
+                (negative.to_string(), value < -STICK_THRESHOLD),
+                (positive.to_string(), value > STICK_THRESHOLD),
+            ],
+            None => vec![],
+        },
+        _ => vec![],
+    }
+}
+
+/// The directory of the config and the ROM database, `$XDG_CONFIG_HOME/chip8-emu`
+/// or `~/.config/chip8-emu`.
+fn config_dir() -> Option<PathBuf> {
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| Some(PathBuf::from(env::var_os("HOME")?).join(".config")))?;
+    Some(dir.join("chip8-emu"))
+}
+
+/// Extends the bundled database with `programs.json` in the config
+/// directory, then with the database at `path`, each taking precedence over
+/// the previous ones. A missing database is only an error if it was given on
+/// the command line.
+fn load_database(path: Option<&str>) -> Database {
+    let mut database = Database::bundled();
+    let default = config_dir().map(|dir| (dir.join("programs.json"), false));
+    for (path, given) in default
+        .into_iter()
+        .chain(path.map(|p| (PathBuf::from(p), true)))
+    {
+        let text = match fs::read_to_string(&path) {
+            Ok(text) => text,
+            Err(e) if !given && e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                eprintln!("Can't read database '{}': {}", path.display(), e);
+                process::exit(1);
+            }
+        };
+        match Database::parse(&text) {
+            Ok(other) => database.extend(other),
+            Err(e) => {
+                eprintln!("Invalid database '{}': {}", path.display(), e);
+                process::exit(1);
+            }
+        }
+    }
+    database
+}
+
+/// Applies the key bindings of `config` to `keymap`, for the ROM at `rom`
+/// with hash `hash`. A missing config is only an error if it was given on the
+/// command line.
+fn load_keymap(keymap: &mut Keymap, config: Option<&str>, rom: &str, hash: &str) {
+    let path = match config
+        .map(PathBuf::from)
+        .or_else(|| Some(config_dir()?.join("config.toml")))
+    {
+        Some(path) => path,
+        None => return,
+    };
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if config.is_none() && e.kind() == io::ErrorKind::NotFound => return,
+        Err(e) => {
+            eprintln!("Can't read config '{}': {}", path.display(), e);
+            process::exit(1);
+        }
+    };
+    let name = Path::new(rom)
+        .file_name()
+        .map_or(rom.into(), |name| name.to_string_lossy());
+    if let Err(e) = keymap.apply_config(&text, &[hash, &name]) {
+        eprintln!("Invalid config '{}': {}", path.display(), e);
+        process::exit(1);
+    }
+}
+
 fn main() {
     let matches = App::new("Chip-8 emulator")
         .version("0.1")
@@ -43,6 +296,168 @@ fn main() {
                 .takes_value(true)
                 .required(true),
         )
+        .arg(
+            Arg::with_name("platform")
+                .long("platform")
+                .value_name("PLATFORM")
+                .help("Selects the machine the ROM was written for")
+                .possible_values(&["chip8", "schip", "xochip"])
+                .default_value("chip8")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("quirks")
+                .long("quirks")
+                .value_name("PROFILE")
+                .help("Emulates the behaviour of a specific interpreter")
+                .possible_values(&["vip", "chip48", "schip", "xochip"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("cpu-hz")
+                .long("cpu-hz")
+                .value_name("HZ")
+                .help("Number of instructions executed per second")
+                .default_value("700")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("PATH")
+                .help("Reads the key bindings from a TOML file instead of the default one")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("database")
+                .long("database")
+                .value_name("PATH")
+                .help("Reads per-ROM settings from a chip-8-database programs.json file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("print-keymap")
+                .long("print-keymap")
+                .help("Prints the key bindings used for the ROM and exits"),
+        )
+        .arg(
+            Arg::with_name("rewind")
+                .long("rewind")
+                .value_name("SECONDS")
+                .help("How much gameplay can be rewound by holding Backspace")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("tone")
+                .long("tone")
+                .value_name("HZ")
+                .help("Frequency of the buzzer")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("volume")
+                .long("volume")
+                .value_name("VOLUME")
+                .help("Volume of the buzzer, between 0.0 and 1.0")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("mute")
+                .long("mute")
+                .help("Starts with the sound muted, press M to toggle"),
+        )
+        .arg(
+            Arg::with_name("wav")
+                .long("wav")
+                .value_name("PATH")
+                .help("Writes the sound to a WAV file instead of playing it")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("palette")
+                .long("palette")
+                .value_name("COLOURS")
+                .help("The four colours of the screen, as in '000000,FFFFFF,AAAAAA,555555'")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("screenshot-scale")
+                .long("screenshot-scale")
+                .value_name("N")
+                .help("Size of a pixel in the screenshots taken with F12")
+                .default_value("8")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("windowed")
+                .long("windowed")
+                .help("Starts in a window instead of fullscreen, press F11 to toggle"),
+        )
+        .arg(
+            Arg::with_name("scale")
+                .long("scale")
+                .value_name("N")
+                .help("Size of a pixel in the window, which fits the 64x32 screen")
+                .default_value("10")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("debug")
+                .long("debug")
+                .help("Starts paused with a debugger reading commands from the terminal"),
+        )
+        .arg(
+            Arg::with_name("trace")
+                .long("trace")
+                .value_name("PATH")
+                .help("Writes a line per instruction run to a file")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-range")
+                .long("trace-range")
+                .value_name("START-END")
+                .help("Only traces the instructions between two hex addresses")
+                .requires("trace")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("trace-max-size")
+                .long("trace-max-size")
+                .value_name("MB")
+                .help("Stops tracing when the trace reaches this size")
+                .default_value("100")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .value_name("SEED")
+                .help("Seeds the random number generator, for reproducible runs")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("record")
+                .long("record")
+                .value_name("PATH")
+                .help("Records the key presses to a movie file, to replay the session later")
+                .conflicts_with("debug")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("replay")
+                .long("replay")
+                .value_name("PATH")
+                .help("Replays a movie file, with the settings it was recorded with")
+                .conflicts_with_all(&["record", "debug"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("wrap-memory")
+                .long("wrap-memory")
+                .help("Wraps memory accesses past the end of memory instead of stopping"),
+        )
         .get_matches();
     let rom = matches.value_of("rom").unwrap();
     let mut program: Vec<u8> = vec![];
@@ -50,30 +465,196 @@ fn main() {
         .expect(&format!("Can't open file: '{}'", rom))
         .read_to_end(&mut program)
         .unwrap();
-    let mut window: PistonWindow = WindowSettings::new("Chip8-emu", (640, 320))
-        .fullscreen(true)
-        .exit_on_esc(true)
-        .build()
-        .unwrap_or_else(|e| panic!("Failed to build PistonWindow: {}", e));
-    let settings = EventSettings::new();
-    // 60 updates per second is enough for Chip8
-    settings.ups(60);
-    settings.bench_mode(true);
-    window.get_event_settings().set_event_settings(settings);
-    let mut chip = Chip8::new(program);
+    let replay = matches.value_of("replay").map(|path| {
+        let text =
+            fs::read_to_string(path).unwrap_or_else(|e| panic!("Can't open '{}': {}", path, e));
+        Movie::parse(&text).unwrap_or_else(|e| {
+            eprintln!("Invalid movie '{}': {}", path, e);
+            process::exit(1);
+        })
+    });
+    // the seed of a recording must be known to write it in the movie
+    let seed: Option<u64> = match matches.value_of("seed") {
+        Some(seed) => Some(seed.parse().expect("Invalid seed")),
+        None if matches.is_present("record") => Some(rand::random()),
+        None => None,
+    };
+    // the settings of known ROMs, command line options take precedence
+    let database = load_database(matches.value_of("database"));
+    let info = database.lookup(&program);
+    if let Some(info) = info {
+        println!("Found '{}' in the ROM database", info.title);
+    }
+    let mut chip = match &replay {
+        // the movie replaces the platform, quirks and seed options
+        Some(movie) => movie.start(&program).unwrap_or_else(|e| {
+            eprintln!(
+                "Can't replay '{}': {}",
+                matches.value_of("replay").unwrap(),
+                e
+            );
+            process::exit(1);
+        }),
+        None => {
+            let platform = match info.and_then(|info| info.platform) {
+                Some(platform) if matches.occurrences_of("platform") == 0 => platform,
+                _ => Platform::from_name(matches.value_of("platform").unwrap()).unwrap(),
+            };
+            let mut chip = Chip8::with_platform(platform, &program).unwrap_or_else(|e| {
+                eprintln!("Can't load '{}': {}", rom, e);
+                process::exit(1);
+            });
+            if let Some(profile) = matches.value_of("quirks") {
+                chip.set_quirks(Quirks::preset(profile).unwrap());
+            } else if let Some(quirks) = info
+                .filter(|info| info.platform == Some(platform))
+                .and_then(|info| info.quirks)
+            {
+                chip.set_quirks(quirks);
+            }
+            if matches.is_present("wrap-memory") {
+                chip.set_memory_policy(MemoryPolicy::Wrap);
+            }
+            if let Some(seed) = seed {
+                chip.set_seed(seed);
+            }
+            chip
+        }
+    };
+    let hash: String = chip
+        .rom_hash()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    let mut keymap = Keymap::new();
+    if let Some(info) = info {
+        keymap.bind_hints(&info.keys);
+    }
+    load_keymap(&mut keymap, matches.value_of("config"), rom, &hash);
+    if matches.is_present("print-keymap") {
+        println!("# {} ({})", rom, hash);
+        print!("{}", keymap);
+        return;
+    }
+    if let Some(path) = matches.value_of("trace") {
+        let mut tracer =
+            Tracer::create(path).unwrap_or_else(|e| panic!("Can't create '{}': {}", path, e));
+        if let Some(range) = matches.value_of("trace-range") {
+            let (start, end) = parse_range(range).expect("Invalid trace range");
+            tracer.set_range(start, end);
+        }
+        let max_size: u64 = matches
+            .value_of("trace-max-size")
+            .unwrap()
+            .parse()
+            .expect("Invalid trace size");
+        tracer.set_max_bytes(max_size * 1024 * 1024);
+        chip.set_tracer(tracer);
+    }
+    let cpu_hz: u32 = matches
+        .value_of("cpu-hz")
+        .unwrap()
+        .parse()
+        .expect("Invalid CPU speed");
+    let cycles = match (&replay, info.and_then(|info| info.tickrate)) {
+        (Some(movie), _) => movie.cycles_per_frame,
+        (None, Some(tickrate)) if matches.occurrences_of("cpu-hz") == 0 => tickrate,
+        _ => chip8::cycles_per_frame(cpu_hz),
+    };
+    let mut recording = matches
+        .value_of("record")
+        .map(|path| (path, Movie::new(&chip, cycles, seed.unwrap())));
+    let mut playback = replay.as_ref().map(Playback::new);
+    // rewinding and loading states would break the movie
+    let movie_mode = recording.is_some() || playback.is_some();
+    // the number of frames run, events are recorded before the next one
+    let mut frame: u64 = 0;
+    let mut rewind = Rewind::with_seconds(
+        matches
+            .value_of("rewind")
+            .unwrap()
+            .parse()
+            .expect("Invalid rewind length"),
+    );
+    let mut buzzer = Buzzer::new(audio::DEFAULT_SAMPLE_RATE);
+    if let Some(tone) = matches.value_of("tone") {
+        buzzer.set_frequency(tone.parse().expect("Invalid tone"));
+    }
+    if let Some(volume) = matches.value_of("volume") {
+        buzzer.set_volume(volume.parse().expect("Invalid volume"));
+    }
+    buzzer.set_muted(matches.is_present("mute"));
+    let mut sink: Box<dyn AudioSink> = match matches.value_of("wav") {
+        Some(path) => Box::new(
+            WavSink::create(path, buzzer.sample_rate())
+                .unwrap_or_else(|e| panic!("Can't create '{}': {}", path, e)),
+        ),
+        None => match RodioSink::new(buzzer.sample_rate()) {
+            Some(sink) => Box::new(sink),
+            None => {
+                eprintln!("No audio device found, sound is disabled");
+                Box::new(NullSink)
+            }
+        },
+    };
+    let palette = match (
+        matches.value_of("palette"),
+        info.and_then(|info| info.palette),
+    ) {
+        (Some(palette), _) => export::parse_palette(palette).expect("Invalid palette"),
+        (None, Some(palette)) => palette,
+        (None, None) => export::DEFAULT_PALETTE,
+    };
+    let colours = piston_colours(&palette);
+    let screenshot_scale: usize = match matches.value_of("screenshot-scale").unwrap().parse() {
+        Ok(scale) if scale > 0 => scale,
+        _ => panic!("Invalid screenshot scale"),
+    };
+    let mut debugger = if matches.is_present("debug") {
+        let mut debugger = Debugger::new();
+        debugger.pause();
+        println!("Paused, type 'help' for the list of commands");
+        prompt();
+        Some((debugger, read_commands()))
+    } else {
+        None
+    };
+    let scale: u32 = match matches.value_of("scale").unwrap().parse() {
+        Ok(scale) if scale > 0 => scale,
+        _ => panic!("Invalid scale"),
+    };
+    let lores = Screen::default();
+    let window_size = [lores.width() as u32 * scale, lores.height() as u32 * scale];
+    let mut fullscreen = !matches.is_present("windowed");
+    let mut window = open_window(fullscreen, window_size);
+    let mut crashed = false;
+    let mut shift = false;
+    let mut rewinding = false;
+    let mut held = HeldInputs::new();
+    let mut gamepads = match Gilrs::new() {
+        Ok(gamepads) => Some(gamepads),
+        Err(e) => {
+            eprintln!("Gamepads are disabled: {}", e);
+            None
+        }
+    };
+    rewind.push(&chip);
     while let Some(e) = window.next() {
-        if let Some(_) = e.render_args() {
+        if let Some(args) = e.render_args() {
             window.draw_2d(&e, |c, g, _| {
+                clear([0.0, 0.0, 0.0, 1.0], g);
                 let screen = chip.screen();
-                for j in 0..32 {
-                    for i in 0..64 {
+                let view = Viewport::fit(args.window_size, screen.width(), screen.height());
+                for j in 0..screen.height() {
+                    for i in 0..screen.width() {
                         rectangle(
-                            if (screen[j] >> (63 - i)) & 0x1 != 0 {
-                                WHITE
-                            } else {
-                                BLACK
-                            },
-                            [i as f64 * 10.0, j as f64 * 10.0, 10.0, 10.0],
+                            colours[screen.pixel(i, j) as usize],
+                            [
+                                view.x + i as f64 * view.pixel,
+                                view.y + j as f64 * view.pixel,
+                                view.pixel,
+                                view.pixel,
+                            ],
                             c.transform,
                             g,
                         );
@@ -82,26 +663,152 @@ fn main() {
             });
         }
         if let Some(_) = e.update_args() {
-            chip.step();
-            chip.decrement_delay();
-            chip.decrement_sound();
-            if chip.sound() > 0 {
-                // TODO: make noise
+            // polled here so that presses are recorded before the next frame
+            while let Some(event) = gamepads.as_mut().and_then(Gilrs::next_event) {
+                for (button, pressed) in pad_inputs(event.event) {
+                    let key = if pressed {
+                        held.press(&keymap, Device::Gamepad, &button)
+                    } else {
+                        held.release(&keymap, Device::Gamepad, &button)
+                    };
+                    if let Some(k) = key {
+                        if playback.is_none() {
+                            let movie = recording.as_mut().map(|(_, movie)| movie);
+                            set_key(&mut chip, movie, frame, k, pressed);
+                        }
+                    }
+                }
+            }
+            if let Some((debugger, commands)) = debugger.as_mut() {
+                for line in commands.try_iter() {
+                    print!("{}", debugger.command(&line, &mut chip));
+                    prompt();
+                }
             }
+            if rewinding {
+                if rewind.rewind(&mut chip) {
+                    crashed = false;
+                }
+            } else if crashed {
+                chip.tick_timers();
+            } else if let Some((debugger, _)) = debugger.as_mut() {
+                if !debugger.is_paused() {
+                    match debugger.run_frame(&mut chip, cycles) {
+                        Ok(StepOutcome::Exited) => break,
+                        Ok(_) => rewind.push(&chip),
+                        // stay paused so that the state can be inspected
+                        Err(err) => {
+                            println!("\nEmulation stopped: {}", err);
+                            debugger.pause();
+                            prompt();
+                        }
+                    }
+                }
+                if let Some(message) = debugger.take_message() {
+                    println!("\n{}", message);
+                    prompt();
+                }
+            } else {
+                if let Some(playback) = playback.as_mut() {
+                    playback.apply(frame, &mut chip);
+                }
+                if playback.as_ref().is_some_and(Playback::is_finished) {
+                    println!("Replay finished, the keyboard is live again");
+                    playback = None;
+                }
+                frame += 1;
+                match chip.run_frame(cycles) {
+                    Ok(StepOutcome::Exited) => break,
+                    Ok(_) => rewind.push(&chip),
+                    Err(err) => {
+                        eprintln!("Emulation stopped: {}", err);
+                        crashed = true;
+                    }
+                }
+            }
+            buzzer.update(&chip);
+            let samples = buzzer.samples((buzzer.sample_rate() as u64 / UPS) as usize);
+            if let Err(e) = sink.write(&samples) {
+                eprintln!("Sound stopped: {}", e);
+                sink = Box::new(NullSink);
+            }
+        }
+        if let Some(Button::Keyboard(Key::M)) = e.press_args() {
+            buzzer.toggle_mute();
+        }
+        if let Some(Button::Keyboard(Key::F12)) = e.press_args() {
+            save_screenshot(&chip, rom, screenshot_scale, &palette);
+        }
+        if let Some(Button::Keyboard(Key::F11)) = e.press_args() {
+            // Piston can't switch an open window, so a new one replaces it.
+            // Keys released after that go to the old window, so they are
+            // released now.
+            for k in held.release_all(&keymap, Device::Keyboard) {
+                if playback.is_none() {
+                    let movie = recording.as_mut().map(|(_, movie)| movie);
+                    set_key(&mut chip, movie, frame, k, false);
+                }
+            }
+            shift = false;
+            rewinding = false;
+            fullscreen = !fullscreen;
+            window = open_window(fullscreen, window_size);
         }
         if let Some(b) = e.press_args() {
             if let Button::Keyboard(key) = b {
-                if let Some(k) = get_chip8_key(key) {
-                    chip.press_key(k);
+                if let Some(k) = held.press(&keymap, Device::Keyboard, &key_name(key)) {
+                    if playback.is_none() {
+                        let movie = recording.as_mut().map(|(_, movie)| movie);
+                        set_key(&mut chip, movie, frame, k, true);
+                    }
+                }
+                if key == Key::LShift || key == Key::RShift {
+                    shift = true;
+                }
+                if key == Key::Backspace && !movie_mode {
+                    rewinding = true;
+                }
+                if let Some(slot) = get_state_slot(key) {
+                    if shift {
+                        save_state(&chip, rom, slot);
+                    } else if movie_mode {
+                        eprintln!("Can't load states while recording or replaying");
+                    } else if load_state(&mut chip, rom, slot) {
+                        crashed = false;
+                        rewind.clear();
+                        rewind.push(&chip);
+                    }
                 }
             }
         }
         if let Some(b) = e.release_args() {
             if let Button::Keyboard(key) = b {
-                if let Some(k) = get_chip8_key(key) {
-                    chip.release_key(k);
+                if key == Key::LShift || key == Key::RShift {
+                    shift = false;
+                }
+                if key == Key::Backspace {
+                    rewinding = false;
+                }
+                if let Some(k) = held.release(&keymap, Device::Keyboard, &key_name(key)) {
+                    if playback.is_none() {
+                        let movie = recording.as_mut().map(|(_, movie)| movie);
+                        set_key(&mut chip, movie, frame, k, false);
+                    }
                 }
             }
         }
     }
+    if let Some(mut tracer) = chip.take_tracer() {
+        if let Some(e) = tracer.error() {
+            eprintln!("Tracing stopped: {}", e);
+        } else if let Err(e) = tracer.flush() {
+            eprintln!("Can't write the trace: {}", e);
+        }
+    }
+    if let Some((path, movie)) = recording {
+        match fs::write(path, movie.to_string()) {
+            Ok(()) => println!("Saved movie to '{}'", path),
+            Err(e) => eprintln!("Can't save movie to '{}': {}", path, e),
+        }
+    }
 }
@@ -0,0 +1,323 @@
+//! Movies: the key presses of a session, with everything needed to replay it
+//! exactly.
+//!
+//! A movie is a text file. A header gives the ROM's SHA-1 hash and the
+//! settings of the machine, followed by one line per key event with the
+//! frame it happened before, counting from 0:
+//!
+//! ```text
+//! chip8-movie 1
+//! rom 0a2f...
+//! platform chip8
+//! quirks 12
+//! memory error
+//! cycles 12
+//! seed 42
+//! 60 +5
+//! 62 -5
+//! ```
+//!
+//! `quirks` holds the quirks as bits, in the order of the fields of `Quirks`.
+
+use crate::headless::KeyEvent;
+use crate::{Chip8, LoadError, MemoryPolicy, Platform, Quirks};
+use std::error::Error;
+use std::fmt::{self, Write};
+
+const MAGIC: &str = "chip8-movie";
+const VERSION: u32 = 1;
+
+/// Errors raised when a movie can't be read or replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// Line `line` (1-based) of the file is invalid.
+    Parse { line: usize, message: String },
+    /// The movie was recorded with a different ROM.
+    RomMismatch,
+    /// The ROM doesn't fit on the platform of the movie.
+    Load(LoadError),
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MovieError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            MovieError::RomMismatch => write!(f, "movie was recorded with a different ROM"),
+            MovieError::Load(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl Error for MovieError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: [u8; 20],
+    pub platform: Platform,
+    pub quirks: Quirks,
+    pub memory_policy: MemoryPolicy,
+    pub cycles_per_frame: usize,
+    pub seed: u64,
+    /// Sorted by frame.
+    pub events: Vec<KeyEvent>,
+}
+
+impl Movie {
+    /// Starts recording `chip`, which must have just been created and given
+    /// `seed` with `Chip8::set_seed`.
+    pub fn new(chip: &Chip8, cycles_per_frame: usize, seed: u64) -> Movie {
+        Movie {
+            rom_hash: *chip.rom_hash(),
+            platform: chip.platform(),
+            quirks: chip.quirks(),
+            memory_policy: chip.memory_policy(),
+            cycles_per_frame,
+            seed,
+            events: vec![],
+        }
+    }
+
+    /// Records a key event before `frame`, which can't be older than the
+    /// last event.
+    pub fn record(&mut self, frame: u64, key: u8, pressed: bool) {
+        debug_assert!(self.events.last().is_none_or(|e| e.frame <= frame));
+        self.events.push(KeyEvent {
+            frame,
+            key,
+            pressed,
+        });
+    }
+
+    /// Creates the machine the movie was recorded on, running `rom`.
+    pub fn start(&self, rom: &[u8]) -> Result<Chip8, MovieError> {
+        let mut chip = Chip8::with_platform(self.platform, rom).map_err(MovieError::Load)?;
+        if *chip.rom_hash() != self.rom_hash {
+            return Err(MovieError::RomMismatch);
+        }
+        chip.set_quirks(self.quirks);
+        chip.set_memory_policy(self.memory_policy);
+        chip.set_seed(self.seed);
+        Ok(chip)
+    }
+
+    pub fn parse(text: &str) -> Result<Movie, MovieError> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(n, line)| (n + 1, line.trim()))
+            .filter(|(_, line)| !line.is_empty());
+        let mut header = |name: &str| -> Result<(usize, String), MovieError> {
+            match lines.next() {
+                Some((n, line)) => match line.strip_prefix(name) {
+                    Some(value) if value.starts_with(' ') => Ok((n, value.trim().to_string())),
+                    _ => Err(error(n, format!("expected '{}'", name))),
+                },
+                None => Err(error(0, format!("missing '{}'", name))),
+            }
+        };
+        let (n, version) = header(MAGIC)?;
+        if version != VERSION.to_string() {
+            return Err(error(n, format!("unsupported version {}", version)));
+        }
+        let (n, hash) = header("rom")?;
+        let rom_hash =
+            Chip8::parse_rom_hash(&hash).ok_or_else(|| error(n, "invalid hash".to_string()))?;
+        let (n, platform) = header("platform")?;
+        let platform = Platform::from_name(&platform)
+            .ok_or_else(|| error(n, format!("unknown platform '{}'", platform)))?;
+        let (n, quirks) = header("quirks")?;
+        let quirks = match quirks.parse::<u8>() {
+            Ok(bits) if bits < 1 << 6 => Quirks::from_bits(bits),
+            _ => return Err(error(n, format!("invalid quirks '{}'", quirks))),
+        };
+        let (n, memory) = header("memory")?;
+        let memory_policy = match memory.as_str() {
+            "error" => MemoryPolicy::Error,
+            "wrap" => MemoryPolicy::Wrap,
+            _ => return Err(error(n, format!("invalid memory policy '{}'", memory))),
+        };
+        let (n, cycles) = header("cycles")?;
+        let cycles_per_frame = cycles
+            .parse()
+            .map_err(|_| error(n, format!("invalid cycles '{}'", cycles)))?;
+        let (n, seed) = header("seed")?;
+        let seed = seed
+            .parse()
+            .map_err(|_| error(n, format!("invalid seed '{}'", seed)))?;
+        let mut events: Vec<KeyEvent> = vec![];
+        for (n, line) in lines {
+            let event = parse_event(line)
+                .ok_or_else(|| error(n, format!("invalid key event '{}'", line)))?;
+            if events.last().is_some_and(|last| last.frame > event.frame) {
+                return Err(error(n, "key events are out of order".to_string()));
+            }
+            events.push(event);
+        }
+        Ok(Movie {
+            rom_hash,
+            platform,
+            quirks,
+            memory_policy,
+            cycles_per_frame,
+            seed,
+            events,
+        })
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} {}", MAGIC, VERSION)?;
+        let mut hash = String::new();
+        for b in self.rom_hash.iter() {
+            write!(hash, "{:02x}", b)?;
+        }
+        writeln!(f, "rom {}", hash)?;
+        writeln!(f, "platform {}", self.platform.name())?;
+        writeln!(f, "quirks {}", self.quirks.to_bits())?;
+        let memory = match self.memory_policy {
+            MemoryPolicy::Error => "error",
+            MemoryPolicy::Wrap => "wrap",
+        };
+        writeln!(f, "memory {}", memory)?;
+        writeln!(f, "cycles {}", self.cycles_per_frame)?;
+        writeln!(f, "seed {}", self.seed)?;
+        for event in self.events.iter() {
+            let sign = if event.pressed { '+' } else { '-' };
+            writeln!(f, "{} {}{:X}", event.frame, sign, event.key)?;
+        }
+        Ok(())
+    }
+}
+
+fn error(line: usize, message: String) -> MovieError {
+    MovieError::Parse { line, message }
+}
+
+/// Parses `FRAME +KEY` or `FRAME -KEY`.
+fn parse_event(line: &str) -> Option<KeyEvent> {
+    let mut parts = line.split_whitespace();
+    let frame = parts.next()?.parse().ok()?;
+    let key = parts.next()?;
+    let pressed = match key.as_bytes().first()? {
+        b'+' => true,
+        b'-' => false,
+        _ => return None,
+    };
+    let key = u8::from_str_radix(&key[1..], 16).ok().filter(|&k| k < 16)?;
+    match parts.next() {
+        Some(_) => None,
+        None => Some(KeyEvent {
+            frame,
+            key,
+            pressed,
+        }),
+    }
+}
+
+/// Feeds the key events of a movie back to a machine.
+pub struct Playback {
+    events: Vec<KeyEvent>,
+    next: usize,
+}
+
+impl Playback {
+    pub fn new(movie: &Movie) -> Playback {
+        Playback {
+            events: movie.events.clone(),
+            next: 0,
+        }
+    }
+
+    /// Presses and releases the keys of the events before `frame`.
+    pub fn apply(&mut self, frame: u64, chip: &mut Chip8) {
+        while let Some(event) = self.events.get(self.next).filter(|e| e.frame <= frame) {
+            if event.pressed {
+                chip.press_key(event.key);
+            } else {
+                chip.release_key(event.key);
+            }
+            self.next += 1;
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next == self.events.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+
+    fn rom() -> Vec<u8> {
+        // draws a random number of sprite rows on each key press
+        asm::assemble("loop: LD V0, K\nRND V1, 0x0F\nDRW V1, V1, 5\nJP loop").unwrap()
+    }
+
+    /// Runs `frames` frames of `chip`, pressing the keys of `playback`.
+    fn run(chip: &mut Chip8, playback: &mut Playback, frames: u64) -> Vec<u8> {
+        for frame in 0..frames {
+            playback.apply(frame, chip);
+            chip.run_frame(12).unwrap();
+        }
+        chip.save_state()
+    }
+
+    #[test]
+    fn replay() {
+        let mut chip = Chip8::new(rom());
+        chip.set_seed(9);
+        let mut movie = Movie::new(&chip, 12, 9);
+        for (frame, key, pressed) in &[(3, 1, true), (5, 1, false), (5, 2, true), (8, 2, false)] {
+            movie.record(*frame, *key, *pressed);
+        }
+        let expected = run(&mut chip, &mut Playback::new(&movie), 10);
+
+        let movie = Movie::parse(&movie.to_string()).unwrap();
+        let mut other = movie.start(&rom()).unwrap();
+        let mut playback = Playback::new(&movie);
+        assert_eq!(run(&mut other, &mut playback, 10), expected);
+        assert!(playback.is_finished());
+    }
+
+    #[test]
+    fn text() {
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &[0x00, 0xE0]).unwrap();
+        chip.set_memory_policy(MemoryPolicy::Wrap);
+        let mut movie = Movie::new(&chip, 30, 7);
+        movie.record(0, 0xA, true);
+        movie.record(12, 0xA, false);
+        let text = movie.to_string();
+        assert!(text.starts_with("chip8-movie 1\nrom "));
+        assert!(text
+            .ends_with("platform schip\nquirks 13\nmemory wrap\ncycles 30\nseed 7\n0 +A\n12 -A\n"));
+        assert_eq!(Movie::parse(&text), Ok(movie));
+    }
+
+    #[test]
+    fn errors() {
+        let movie = Movie::new(&Chip8::new(vec![0x00, 0xE0]), 12, 0).to_string();
+        let parse_error = |text: &str| match Movie::parse(text) {
+            Err(MovieError::Parse { line, .. }) => line,
+            other => panic!("{:?}", other),
+        };
+        assert_eq!(parse_error("nope"), 1);
+        assert_eq!(
+            parse_error(&movie.replace("chip8-movie 1", "chip8-movie 2")),
+            1
+        );
+        assert_eq!(
+            parse_error(&movie.replace("platform chip8", "platform nes")),
+            3
+        );
+        assert_eq!(parse_error(&format!("{}5 +1\n4 +2\n", movie)), 9);
+        assert_eq!(parse_error(&format!("{}5 +G\n", movie)), 8);
+        assert_eq!(parse_error(&movie.replace("seed 0\n", "")), 0);
+        assert_eq!(
+            Movie::parse(&movie).unwrap().start(&[0x00, 0xEE]).err(),
+            Some(MovieError::RomMismatch)
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::Quirks;
+
+/// The machine a ROM was written for. The platform decides the memory map
+/// and which instructions the interpreter accepts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Platform {
+    /// The original CHIP-8 interpreter with 4 KiB of memory.
+    #[default]
+    Chip8,
+    /// SUPER-CHIP 1.1, which adds a 128x64 mode, scrolling and big sprites.
+    SuperChip,
+    /// XO-CHIP, which extends SUPER-CHIP with 64 KiB of memory, a second bit
+    /// plane and programmable audio.
+    XoChip,
+}
+
+impl Platform {
+    /// The name used on the command line and in movie files.
+    pub fn name(self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+        }
+    }
+
+    /// Looks up a platform by `Platform::name`.
+    pub fn from_name(name: &str) -> Option<Platform> {
+        [Platform::Chip8, Platform::SuperChip, Platform::XoChip]
+            .iter()
+            .copied()
+            .find(|platform| platform.name() == name)
+    }
+
+    /// Size of the address space in bytes.
+    pub fn memory_size(self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    /// Address at which programs are loaded and execution starts.
+    pub fn program_start(self) -> usize {
+        0x200
+    }
+
+    /// The quirks programs written for this platform usually expect.
+    pub fn default_quirks(self) -> Quirks {
+        match self {
+            Platform::Chip8 => Quirks::default(),
+            Platform::SuperChip => Quirks::super_chip(),
+            Platform::XoChip => Quirks::xo_chip(),
+        }
+    }
+
+    pub(crate) fn id(self) -> u8 {
+        match self {
+            Platform::Chip8 => 0,
+            Platform::SuperChip => 1,
+            Platform::XoChip => 2,
+        }
+    }
+
+    pub(crate) fn from_id(id: u8) -> Option<Platform> {
+        match id {
+            0 => Some(Platform::Chip8),
+            1 => Some(Platform::SuperChip),
+            2 => Some(Platform::XoChip),
+            _ => None,
+        }
+    }
+
+    /// The largest program that fits in memory.
+    pub fn max_rom_size(self) -> usize {
+        self.memory_size() - self.program_start()
+    }
+}
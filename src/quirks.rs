@@ -0,0 +1,105 @@
+/// Behaviour of the instructions that differ between CHIP-8 interpreters.
+///
+/// The `Default` profile matches what this crate has always done, which also
+/// happens to be what XO-CHIP programs expect.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Quirks {
+    /// `8XY6`/`8XYE` shift VX in place instead of storing VY shifted into VX.
+    pub shift: bool,
+    /// `FX55`/`FX65` leave I pointing past the last register accessed.
+    pub load_store: bool,
+    /// `BNNN` behaves as `BXNN` and jumps to XNN + VX instead of NNN + V0.
+    pub jump: bool,
+    /// Sprites are clipped at the edges of the screen instead of wrapping.
+    pub clipping: bool,
+    /// `8XY1`/`8XY2`/`8XY3` reset VF to 0.
+    pub vf_reset: bool,
+    /// `DXYN` blocks the interpreter until the next timer tick.
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    /// The original interpreter on the COSMAC VIP.
+    pub fn cosmac_vip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: true,
+            jump: false,
+            clipping: true,
+            vf_reset: true,
+            display_wait: true,
+        }
+    }
+
+    /// CHIP-48 on the HP-48 calculators.
+    pub fn chip48() -> Quirks {
+        Quirks {
+            shift: true,
+            load_store: false,
+            jump: true,
+            clipping: true,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+
+    /// SUPER-CHIP 1.1.
+    pub fn super_chip() -> Quirks {
+        Quirks::chip48()
+    }
+
+    /// XO-CHIP as implemented by Octo.
+    pub fn xo_chip() -> Quirks {
+        Quirks {
+            shift: false,
+            load_store: true,
+            jump: false,
+            clipping: false,
+            vf_reset: false,
+            display_wait: false,
+        }
+    }
+
+    pub(crate) fn to_bits(self) -> u8 {
+        [
+            self.shift,
+            self.load_store,
+            self.jump,
+            self.clipping,
+            self.vf_reset,
+            self.display_wait,
+        ]
+        .iter()
+        .enumerate()
+        .fold(0, |bits, (i, set)| bits | ((*set as u8) << i))
+    }
+
+    pub(crate) fn from_bits(bits: u8) -> Quirks {
+        let set = |i: u8| bits & (1 << i) != 0;
+        Quirks {
+            shift: set(0),
+            load_store: set(1),
+            jump: set(2),
+            clipping: set(3),
+            vf_reset: set(4),
+            display_wait: set(5),
+        }
+    }
+
+    /// Looks up a preset by the name used on the command line.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name {
+            "vip" => Some(Quirks::cosmac_vip()),
+            "chip48" => Some(Quirks::chip48()),
+            "schip" => Some(Quirks::super_chip()),
+            "xochip" => Some(Quirks::xo_chip()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::xo_chip()
+    }
+}
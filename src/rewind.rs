@@ -0,0 +1,224 @@
+//! A history of recent frames that gameplay can be stepped back through.
+//!
+//! Only the newest save state is kept in full. Every older frame is stored as
+//! the XOR of its state with the state of the frame after it, with runs of
+//! zeros compressed away. As most of memory doesn't change between frames,
+//! this usually takes a few dozen bytes per frame.
+
+use crate::{Chip8, TIMER_HZ};
+use std::collections::VecDeque;
+
+/// The default cap on the memory used by the history.
+pub const DEFAULT_MAX_BYTES: usize = 32 * 1024 * 1024;
+
+pub struct Rewind {
+    latest: Option<Vec<u8>>,
+    // oldest first
+    deltas: VecDeque<Vec<u8>>,
+    max_frames: usize,
+    max_bytes: usize,
+    bytes: usize,
+}
+
+impl Rewind {
+    /// Keeps at most `max_frames` frames, using about `max_bytes` of memory.
+    pub fn new(max_frames: usize, max_bytes: usize) -> Rewind {
+        Rewind {
+            latest: None,
+            deltas: VecDeque::new(),
+            max_frames,
+            max_bytes,
+            bytes: 0,
+        }
+    }
+
+    /// Keeps `seconds` worth of frames, using at most `DEFAULT_MAX_BYTES`.
+    pub fn with_seconds(seconds: f32) -> Rewind {
+        let frames = (seconds.max(0.0) * TIMER_HZ as f32) as usize;
+        Rewind::new(frames, DEFAULT_MAX_BYTES)
+    }
+
+    /// Number of frames that can be rewound.
+    pub fn len(&self) -> usize {
+        self.deltas.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.deltas.is_empty()
+    }
+
+    /// Approximate number of bytes used by the history.
+    pub fn memory_usage(&self) -> usize {
+        self.bytes + self.latest.as_ref().map_or(0, Vec::len)
+    }
+
+    pub fn clear(&mut self) {
+        self.latest = None;
+        self.deltas.clear();
+        self.bytes = 0;
+    }
+
+    /// Records the current state of `chip` as the newest frame.
+    pub fn push(&mut self, chip: &Chip8) {
+        let state = chip.save_state();
+        if let Some(latest) = self.latest.take() {
+            if latest.len() == state.len() && self.max_frames > 0 {
+                let delta = compress(&xor(&latest, &state));
+                self.bytes += delta.len();
+                self.deltas.push_back(delta);
+            } else {
+                self.deltas.clear();
+                self.bytes = 0;
+            }
+        }
+        self.latest = Some(state);
+        while self.deltas.len() > self.max_frames || self.memory_usage() > self.max_bytes {
+            match self.deltas.pop_front() {
+                Some(delta) => self.bytes -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Restores `chip` to the frame before the newest one, which is dropped
+    /// from the history. Returns false if there is nothing to rewind.
+    pub fn rewind(&mut self, chip: &mut Chip8) -> bool {
+        let (delta, latest) = match (self.deltas.pop_back(), self.latest.as_mut()) {
+            (Some(delta), Some(latest)) => (delta, latest),
+            _ => return false,
+        };
+        self.bytes -= delta.len();
+        for (b, d) in latest.iter_mut().zip(decompress(&delta)) {
+            *b ^= d;
+        }
+        if chip.load_state(latest).is_err() {
+            self.clear();
+            return false;
+        }
+        true
+    }
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(x, y)| x ^ y).collect()
+}
+
+/// Encodes `data` as a sequence of (zero run length, literal length, literal
+/// bytes), with the lengths stored as LEB128 varints.
+fn compress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = data[i..].iter().take_while(|b| **b == 0).count();
+        i += zeros;
+        let literals = data[i..].iter().take_while(|b| **b != 0).count();
+        write_varint(&mut out, zeros);
+        write_varint(&mut out, literals);
+        out.extend_from_slice(&data[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+fn decompress(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut i = 0;
+    while i < data.len() {
+        let zeros = read_varint(data, &mut i);
+        let literals = read_varint(data, &mut i);
+        out.resize(out.len() + zeros, 0);
+        out.extend_from_slice(&data[i..i + literals]);
+        i += literals;
+    }
+    out
+}
+
+fn write_varint(out: &mut Vec<u8>, mut n: usize) {
+    while n >= 0x80 {
+        out.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    out.push(n as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+    let mut n = 0;
+    let mut shift = 0;
+    loop {
+        let b = data[*i];
+        *i += 1;
+        n |= ((b & 0x7F) as usize) << shift;
+        if b & 0x80 == 0 {
+            return n;
+        }
+        shift += 7;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compression() {
+        let mut data = vec![0; 1000];
+        data[3] = 1;
+        data[4] = 2;
+        data[999] = 3;
+        let compressed = compress(&data);
+        assert!(compressed.len() < 10);
+        assert_eq!(decompress(&compressed), data);
+        assert_eq!(decompress(&compress(&[])), Vec::<u8>::new());
+        assert_eq!(decompress(&compress(&[7, 0])), vec![7, 0]);
+    }
+
+    #[test]
+    fn rewind() {
+        // count up in V0 forever
+        let mut chip = Chip8::new(vec![0x70, 0x01, 0x12, 0x00]);
+        chip.set_seed(0);
+        let mut rewind = Rewind::new(100, DEFAULT_MAX_BYTES);
+        rewind.push(&chip);
+        for _ in 0..5 {
+            chip.run_frame(2).unwrap();
+            rewind.push(&chip);
+        }
+        assert_eq!(rewind.len(), 5);
+        assert!(rewind.memory_usage() < 2 * chip.save_state().len());
+        let state = chip.save_state();
+        assert!(rewind.rewind(&mut chip));
+        assert!(rewind.rewind(&mut chip));
+        let mut expected = Chip8::new(vec![0x70, 0x01, 0x12, 0x00]);
+        expected.set_seed(0);
+        expected.run_frame(2).unwrap();
+        expected.run_frame(2).unwrap();
+        expected.run_frame(2).unwrap();
+        assert_eq!(chip.save_state(), expected.save_state());
+        assert_eq!(rewind.len(), 3);
+        for _ in 0..2 {
+            chip.run_frame(2).unwrap();
+            rewind.push(&chip);
+        }
+        assert_eq!(chip.save_state(), state);
+    }
+
+    #[test]
+    fn bounded() {
+        let mut chip = Chip8::new(vec![0x70, 0x01, 0x12, 0x00]);
+        let mut rewind = Rewind::new(3, DEFAULT_MAX_BYTES);
+        for _ in 0..10 {
+            chip.run_frame(2).unwrap();
+            rewind.push(&chip);
+        }
+        assert_eq!(rewind.len(), 3);
+        for _ in 0..3 {
+            assert!(rewind.rewind(&mut chip));
+        }
+        assert!(!rewind.rewind(&mut chip));
+
+        let mut rewind = Rewind::new(100, 0);
+        rewind.push(&chip);
+        rewind.push(&chip);
+        assert!(rewind.is_empty());
+    }
+}
@@ -0,0 +1,68 @@
+/// The random number generator behind `CXNN`, a xorshift64* seeded with
+/// splitmix64. Its whole state is one number, which goes in save states so
+/// that replays and rewinds draw the same numbers again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Rng {
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Rng::from_state(z ^ (z >> 31))
+    }
+
+    /// Restores a generator from `state()`. xorshift never leaves 0, so it
+    /// is replaced by another value.
+    pub(crate) fn from_state(state: u64) -> Rng {
+        Rng {
+            state: if state == 0 {
+                0x2545_F491_4F6C_DD1D
+            } else {
+                state
+            },
+        }
+    }
+
+    pub(crate) fn state(&self) -> u64 {
+        self.state
+    }
+
+    pub(crate) fn next_u8(&mut self) -> u8 {
+        self.state ^= self.state >> 12;
+        self.state ^= self.state << 25;
+        self.state ^= self.state >> 27;
+        (self.state.wrapping_mul(0x2545_F491_4F6C_DD1D) >> 56) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(rng: &mut Rng) -> Vec<u8> {
+        (0..64).map(|_| rng.next_u8()).collect()
+    }
+
+    #[test]
+    fn seeded() {
+        assert_eq!(bytes(&mut Rng::new(42)), bytes(&mut Rng::new(42)));
+        assert_ne!(bytes(&mut Rng::new(42)), bytes(&mut Rng::new(43)));
+        let mut rng = Rng::new(0);
+        rng.next_u8();
+        let mut copy = Rng::from_state(rng.state());
+        assert_eq!(bytes(&mut rng), bytes(&mut copy));
+    }
+
+    #[test]
+    fn spread() {
+        let mut rng = Rng::new(7);
+        let mut counts = [0; 256];
+        for _ in 0..256 * 64 {
+            counts[rng.next_u8() as usize] += 1;
+        }
+        assert!(counts.iter().all(|&n| n > 16 && n < 128));
+    }
+}
@@ -0,0 +1,320 @@
+//! Save states: a snapshot of the whole machine in a versioned binary format.
+//!
+//! A state starts with a header made of the magic bytes `C8ST`, the format
+//! version, the platform and the SHA-1 hash of the ROM. The body contains all
+//! of the machine's fields in a fixed order, with multi-byte values stored in
+//! little endian.
+
+use crate::rng::Rng;
+use crate::{Chip8, MemoryPolicy, Platform, Quirks, Screen};
+use std::error::Error;
+use std::fmt;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const VERSION: u16 = 2;
+
+/// Errors raised when a save state can't be restored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// The data does not start with the save state magic bytes.
+    BadMagic,
+    /// The state was written by an incompatible version of the format.
+    UnsupportedVersion(u16),
+    /// The state was saved on a different platform.
+    PlatformMismatch,
+    /// The state was saved while running a different ROM.
+    RomMismatch,
+    /// The data ends before the end of the state.
+    Truncated,
+    /// A field holds a value the machine can't be in.
+    Corrupted,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "not a save state"),
+            StateError::UnsupportedVersion(v) => write!(f, "unsupported save state version {}", v),
+            StateError::PlatformMismatch => write!(f, "save state is for a different platform"),
+            StateError::RomMismatch => write!(f, "save state is for a different ROM"),
+            StateError::Truncated => write!(f, "save state is truncated"),
+            StateError::Corrupted => write!(f, "save state is corrupted"),
+        }
+    }
+}
+
+impl Error for StateError {}
+
+pub(crate) struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    pub(crate) fn u8(&mut self, v: u8) {
+        self.buf.push(v);
+    }
+
+    pub(crate) fn u16(&mut self, v: u16) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, v: u32) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, v: u64) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn u128(&mut self, v: u128) {
+        self.buf.extend_from_slice(&v.to_le_bytes());
+    }
+
+    pub(crate) fn bytes(&mut self, v: &[u8]) {
+        self.buf.extend_from_slice(v);
+    }
+}
+
+pub(crate) struct Reader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn bytes(&mut self, len: usize) -> Result<&'a [u8], StateError> {
+        if self.buf.len() < len {
+            return Err(StateError::Truncated);
+        }
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], StateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, StateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn bool(&mut self) -> Result<bool, StateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StateError::Corrupted),
+        }
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, StateError> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, StateError> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, StateError> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+
+    pub(crate) fn u128(&mut self) -> Result<u128, StateError> {
+        Ok(u128::from_le_bytes(self.array()?))
+    }
+}
+
+impl Chip8 {
+    /// Serializes the whole machine, see the `state` module for the format.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut w = Writer { buf: vec![] };
+        w.bytes(MAGIC);
+        w.u16(VERSION);
+        w.u8(self.platform.id());
+        w.bytes(&self.rom_hash);
+        w.u8(self.quirks.to_bits());
+        w.u8(match self.memory_policy {
+            MemoryPolicy::Error => 0,
+            MemoryPolicy::Wrap => 1,
+        });
+        w.bytes(&self.mem);
+        w.bytes(&self.v);
+        w.u16(self.i);
+        w.u32(self.pc as u32);
+        self.screen.save(&mut w);
+        w.u8(self.delay);
+        w.u8(self.sound);
+        w.u8(self.sp as u8);
+        for addr in self.stack.iter() {
+            w.u16(*addr);
+        }
+        w.u16(self.keyboard);
+        w.u8(self.halted.unwrap_or(0xFF));
+        w.u8(self.waiting_vblank as u8);
+        w.u8(self.exited as u8);
+        w.bytes(&self.rpl);
+        w.bytes(&self.audio_pattern);
+        w.u8(self.pitch);
+        w.u64(self.rng.state());
+        w.buf
+    }
+
+    /// Restores a state created by `save_state`. The state must have been
+    /// saved on the same platform while running the same ROM. On error the
+    /// machine is left untouched.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let mut r = Reader { buf: data };
+        if r.bytes(MAGIC.len()).ok() != Some(&MAGIC[..]) {
+            return Err(StateError::BadMagic);
+        }
+        let version = r.u16()?;
+        if version != VERSION {
+            return Err(StateError::UnsupportedVersion(version));
+        }
+        if Platform::from_id(r.u8()?) != Some(self.platform) {
+            return Err(StateError::PlatformMismatch);
+        }
+        if r.array::<20>()? != self.rom_hash {
+            return Err(StateError::RomMismatch);
+        }
+        let quirks = Quirks::from_bits(r.u8()?);
+        let memory_policy = match r.u8()? {
+            0 => MemoryPolicy::Error,
+            1 => MemoryPolicy::Wrap,
+            _ => return Err(StateError::Corrupted),
+        };
+        let mem = r.bytes(self.mem.len())?.to_vec();
+        let v = r.array()?;
+        let i = r.u16()?;
+        let pc = r.u32()? as usize;
+        let screen = Screen::load(&mut r)?;
+        let delay = r.u8()?;
+        let sound = r.u8()?;
+        let sp = r.u8()? as usize;
+        let mut stack = [0; 16];
+        for addr in stack.iter_mut() {
+            *addr = r.u16()?;
+        }
+        let keyboard = r.u16()?;
+        let halted = match r.u8()? {
+            0xFF => None,
+            x if x < 16 => Some(x),
+            _ => return Err(StateError::Corrupted),
+        };
+        let waiting_vblank = r.bool()?;
+        let exited = r.bool()?;
+        let rpl = r.array()?;
+        let audio_pattern = r.array()?;
+        let pitch = r.u8()?;
+        let rng = Rng::from_state(r.u64()?);
+        if pc >= mem.len() || sp > stack.len() || !r.buf.is_empty() {
+            return Err(StateError::Corrupted);
+        }
+        self.quirks = quirks;
+        self.memory_policy = memory_policy;
+        self.mem = mem;
+        self.v = v;
+        self.i = i;
+        self.pc = pc;
+        self.screen = screen;
+        self.delay = delay;
+        self.sound = sound;
+        self.sp = sp;
+        self.stack = stack;
+        self.keyboard = keyboard;
+        self.halted = halted;
+        self.waiting_vblank = waiting_vblank;
+        self.exited = exited;
+        self.rpl = rpl;
+        self.audio_pattern = audio_pattern;
+        self.pitch = pitch;
+        self.rng = rng;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let prog = vec![0x60, 0x05, 0xA2, 0x00, 0xD0, 0x05, 0x22, 0x00];
+        let mut chip = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        for _ in 0..4 {
+            chip.step().unwrap();
+        }
+        chip.press_key(3);
+        let state = chip.save_state();
+        let mut other = Chip8::with_platform(Platform::SuperChip, &prog).unwrap();
+        other.load_state(&state).unwrap();
+        assert_eq!(other.save_state(), state);
+        assert_eq!(other.pc, 0x200);
+        assert_eq!(other.sp, 1);
+        assert_eq!(other.v[0], 5);
+        assert_eq!(other.screen.row(5), chip.screen.row(5));
+        assert!(other.is_pressed(3));
+    }
+
+    #[test]
+    fn restores_rng() {
+        let prog = vec![0xC0, 0xFF, 0x12, 0x00];
+        let mut chip = Chip8::new(prog.clone());
+        chip.set_seed(1);
+        let state = chip.save_state();
+        let draw = |chip: &mut Chip8| {
+            (0..8)
+                .map(|_| {
+                    chip.step().unwrap();
+                    chip.step().unwrap();
+                    chip.v[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+        let numbers = draw(&mut chip);
+        let mut other = Chip8::new(prog);
+        other.load_state(&state).unwrap();
+        assert_eq!(draw(&mut other), numbers);
+    }
+
+    #[test]
+    fn header() {
+        let chip = Chip8::new(vec![0x00, 0xE0]);
+        let state = chip.save_state();
+        assert_eq!(&state[0..4], b"C8ST");
+        assert_eq!(state[4..6], VERSION.to_le_bytes());
+        assert_eq!(state[6], Platform::Chip8.id());
+        assert_eq!(state[7..27], chip.rom_hash()[..]);
+    }
+
+    #[test]
+    fn rejects_other_rom() {
+        let state = Chip8::new(vec![0x00, 0xE0]).save_state();
+        let mut chip = Chip8::new(vec![0x00, 0xEE]);
+        assert_eq!(chip.load_state(&state), Err(StateError::RomMismatch));
+    }
+
+    #[test]
+    fn rejects_other_platform() {
+        let state = Chip8::new(vec![0x00, 0xE0]).save_state();
+        let mut chip = Chip8::with_platform(Platform::XoChip, &[0x00, 0xE0]).unwrap();
+        assert_eq!(chip.load_state(&state), Err(StateError::PlatformMismatch));
+    }
+
+    #[test]
+    fn rejects_bad_data() {
+        let mut chip = Chip8::new(vec![0x00, 0xE0]);
+        let state = chip.save_state();
+        assert_eq!(chip.load_state(b"nope"), Err(StateError::BadMagic));
+        assert_eq!(
+            chip.load_state(&state[..state.len() - 1]),
+            Err(StateError::Truncated)
+        );
+        let mut future = state.clone();
+        future[4] = 0xFF;
+        assert_eq!(
+            chip.load_state(&future),
+            Err(StateError::UnsupportedVersion(0x00FF))
+        );
+    }
+}
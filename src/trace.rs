@@ -0,0 +1,246 @@
+//! Instruction traces, to compare the emulator against other interpreters.
+//!
+//! Once installed with `Chip8::set_tracer`, a `Tracer` writes one line per
+//! instruction with the state of the machine before it runs:
+//!
+//! ```text
+//! 00000000 0200 6A02 LD VA, 0x02          V:00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:0 DT:00 ST:00
+//! ```
+//!
+//! The columns are the cycle count, PC, opcode, disassembly, V0 to VF, I,
+//! SP and the delay and sound timers, all in hexadecimal except the cycle
+//! count. Only the text after the disassembly needs to match to diff two
+//! logs, e.g. with `cut -c 41-`.
+
+use crate::disasm::{self, Syntax};
+use crate::Chip8;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+const TRUNCATED: &str = "trace truncated\n";
+
+/// Writes a line per instruction, see the module documentation.
+pub struct Tracer {
+    out: Box<dyn Write>,
+    range: Option<RangeInclusive<u16>>,
+    max_bytes: Option<u64>,
+    written: u64,
+    cycle: u64,
+    stopped: bool,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new<W: Write + 'static>(out: W) -> Tracer {
+        Tracer {
+            out: Box::new(out),
+            range: None,
+            max_bytes: None,
+            written: 0,
+            cycle: 0,
+            stopped: false,
+            error: None,
+        }
+    }
+
+    pub fn create<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+        Ok(Tracer::new(BufWriter::new(File::create(path)?)))
+    }
+
+    /// Only traces the instructions between `start` and `end`, inclusive.
+    /// Cycles are still counted outside of the range.
+    pub fn set_range(&mut self, start: u16, end: u16) {
+        self.range = Some(start..=end);
+    }
+
+    /// Stops tracing before the trace grows past `max_bytes`, writing a last
+    /// line saying it was truncated.
+    pub fn set_max_bytes(&mut self, max_bytes: u64) {
+        self.max_bytes = Some(max_bytes);
+    }
+
+    /// The number of instructions seen so far, traced or not.
+    pub fn cycle(&self) -> u64 {
+        self.cycle
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.written
+    }
+
+    /// The error that stopped the trace, if any.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.error.as_ref()
+    }
+
+    /// Flushes the trace.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    /// Traces `opcode`, the instruction at the program counter, called before
+    /// it runs. `addr` is the program counter resolved to a memory address.
+    pub(crate) fn record(&mut self, chip: &Chip8, addr: usize, opcode: u16) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if self.stopped
+            || self
+                .range
+                .as_ref()
+                .is_some_and(|r| !r.contains(&(addr as u16)))
+        {
+            return;
+        }
+        let line = line(cycle, chip, addr, opcode);
+        let truncated = match self.max_bytes {
+            Some(max) => self.written + (line.len() + TRUNCATED.len()) as u64 > max,
+            _ => false,
+        };
+        let text = if truncated { TRUNCATED } else { &line };
+        match self.out.write_all(text.as_bytes()) {
+            Ok(()) => self.written += text.len() as u64,
+            Err(e) => {
+                self.error = Some(e);
+                self.stopped = true;
+            }
+        }
+        if truncated {
+            self.stopped = true;
+            let _ = self.out.flush();
+        }
+    }
+}
+
+fn line(cycle: u64, chip: &Chip8, addr: usize, opcode: u16) -> String {
+    // the word after the opcode is only needed for `F000 NNNN`
+    let mut bytes = opcode.to_be_bytes().to_vec();
+    bytes.extend_from_slice(chip.memory().get(addr + 2..addr + 4).unwrap_or(&[]));
+    let (text, _) = disasm::format_at(&bytes, 0, Syntax::Cowgod).unwrap();
+    let mut line = format!("{:08} {:04X} {:04X} {:<20} V:", cycle, addr, opcode, text);
+    for (x, v) in chip.v().iter().enumerate() {
+        let sep = if x == 0 { "" } else { " " };
+        write!(line, "{}{:02X}", sep, v).unwrap();
+    }
+    writeln!(
+        line,
+        " I:{:04X} SP:{:X} DT:{:02X} ST:{:02X}",
+        chip.i(),
+        chip.sp(),
+        chip.delay(),
+        chip.sound()
+    )
+    .unwrap();
+    line
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::asm;
+    use crate::MemoryPolicy;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    #[derive(Clone, Default)]
+    struct Shared(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.borrow().clone())
+                .unwrap()
+                .lines()
+                .map(String::from)
+                .collect()
+        }
+    }
+
+    fn chip(source: &str) -> Chip8 {
+        Chip8::new(asm::assemble(source).unwrap())
+    }
+
+    #[test]
+    fn trace() {
+        let mut chip = chip("LD VA, 2\nLD I, 0x123\nLD DT, VA\nCALL 0x200");
+        let out = Shared::default();
+        chip.set_tracer(Tracer::new(out.clone()));
+        for _ in 0..4 {
+            chip.step().unwrap();
+        }
+        let lines = out.lines();
+        assert_eq!(
+            lines[0],
+            "00000000 0200 6A02 LD VA, 0x02          \
+             V:00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0000 SP:0 DT:00 ST:00"
+        );
+        assert_eq!(
+            lines[3],
+            "00000003 0206 2200 CALL 0x200           \
+             V:00 00 00 00 00 00 00 00 00 00 02 00 00 00 00 00 I:0123 SP:0 DT:02 ST:00"
+        );
+        assert_eq!(chip.take_tracer().unwrap().cycle(), 4);
+    }
+
+    #[test]
+    fn wrap() {
+        let mut chip = chip("JP 0xFFE");
+        chip.set_memory_policy(MemoryPolicy::Wrap);
+        chip.mem[0xFFE..].copy_from_slice(&[0x60, 0x01]);
+        chip.mem[..2].copy_from_slice(&[0x00, 0xE0]);
+        let out = Shared::default();
+        chip.set_tracer(Tracer::new(out.clone()));
+        for _ in 0..3 {
+            chip.step().unwrap();
+        }
+        let lines = out.lines();
+        assert!(lines[1].starts_with("00000001 0FFE 6001 LD V0, 0x01 "));
+        assert!(lines[2].starts_with("00000002 0000 00E0 CLS "));
+    }
+
+    #[test]
+    fn range() {
+        let mut chip = chip("CLS\nCLS\nCLS\nJP 0x200");
+        let out = Shared::default();
+        let mut tracer = Tracer::new(out.clone());
+        tracer.set_range(0x202, 0x204);
+        chip.set_tracer(tracer);
+        for _ in 0..8 {
+            chip.step().unwrap();
+        }
+        let lines = out.lines();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[0].starts_with("00000001 0202"));
+        assert!(lines[1].starts_with("00000002 0204"));
+        assert!(lines[2].starts_with("00000005 0202"));
+        assert!(lines[3].starts_with("00000006 0204"));
+    }
+
+    #[test]
+    fn max_bytes() {
+        let mut chip = chip("JP 0x200");
+        let out = Shared::default();
+        let mut tracer = Tracer::new(out.clone());
+        tracer.set_max_bytes(250);
+        chip.set_tracer(tracer);
+        for _ in 0..10 {
+            chip.step().unwrap();
+        }
+        let lines = out.lines();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[2], "trace truncated");
+        assert_eq!(chip.take_tracer().unwrap().bytes_written(), 2 * 114 + 16);
+    }
+}
@@ -0,0 +1,45 @@
+//! Fitting the screen in a window of any size.
+
+/// Where the screen goes in a window: pixels are `pixel` wide squares and the
+/// top left corner is at (`x`, `y`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Viewport {
+    pub x: f64,
+    pub y: f64,
+    pub pixel: f64,
+}
+
+impl Viewport {
+    /// Centres a `width` x `height` screen in a `window`, scaled by the
+    /// largest whole factor that fits, or 1 if the window is too small. The
+    /// rest of the window is left for borders.
+    pub fn fit(window: [f64; 2], width: usize, height: usize) -> Viewport {
+        let (width, height) = (width as f64, height as f64);
+        let pixel = (window[0] / width).min(window[1] / height).floor().max(1.0);
+        Viewport {
+            x: ((window[0] - width * pixel) / 2.0).floor(),
+            y: ((window[1] - height * pixel) / 2.0).floor(),
+            pixel,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit() {
+        let view = |window, width, height| {
+            let v = Viewport::fit(window, width, height);
+            (v.x, v.y, v.pixel)
+        };
+        assert_eq!(view([640.0, 320.0], 64, 32), (0.0, 0.0, 10.0));
+        assert_eq!(view([640.0, 320.0], 128, 64), (0.0, 0.0, 5.0));
+        // wider and taller windows get borders on the sides and at the top
+        assert_eq!(view([1920.0, 1080.0], 64, 32), (0.0, 60.0, 30.0));
+        assert_eq!(view([700.0, 330.0], 64, 32), (30.0, 5.0, 10.0));
+        assert_eq!(view([200.0, 400.0], 64, 32), (4.0, 152.0, 3.0));
+        assert_eq!(view([50.0, 20.0], 64, 32), (-7.0, -6.0, 1.0));
+    }
+}
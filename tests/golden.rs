@@ -0,0 +1,145 @@
+//! Runs the test ROMs in `tests/roms` headlessly and compares their screens
+//! with the golden images in `tests/golden`, one text file per case with `#`
+//! for lit pixels.
+//!
+//! The `own_` cases run this crate's own checks in `tests/roms/own`, written
+//! for its assembler alongside the interpreter. They catch regressions, but
+//! aren't an independent check of the interpreter against the specification.
+//!
+//! ROMs are either sources for the assembler or binary `.ch8` files. Run with
+//! `CHIP8_UPDATE_GOLDEN=1` to write the golden images instead of checking
+//! them. On a mismatch, the actual screen is written to `target/golden` as
+//! text and PNG.
+
+use chip8::export::{self, Image};
+use chip8::headless::{KeyScript, Runner};
+use chip8::{asm, Chip8, Platform, Quirks};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const CYCLES_PER_FRAME: usize = 12;
+const FRAMES: u64 = 300;
+
+struct Case {
+    /// The name of the golden image.
+    name: &'static str,
+    rom: &'static str,
+    platform: Platform,
+    quirks: Option<Quirks>,
+    keys: &'static str,
+}
+
+impl Case {
+    fn new(name: &'static str, rom: &'static str) -> Case {
+        Case {
+            name,
+            rom,
+            platform: Platform::Chip8,
+            quirks: None,
+            keys: "",
+        }
+    }
+
+    fn quirks(self, quirks: Quirks) -> Case {
+        Case {
+            quirks: Some(quirks),
+            ..self
+        }
+    }
+
+    fn keys(self, keys: &'static str) -> Case {
+        Case { keys, ..self }
+    }
+
+    fn run(&self) -> Chip8 {
+        let path = root().join("tests/roms").join(self.rom);
+        let rom = if path.extension().is_some_and(|ext| ext == "ch8") {
+            fs::read(&path).unwrap_or_else(|e| panic!("can't read {}: {}", path.display(), e))
+        } else {
+            asm::assemble_file(&path).unwrap_or_else(|e| panic!("{}", e))
+        };
+        let mut chip = Chip8::with_platform(self.platform, &rom).unwrap();
+        chip.set_seed(0);
+        if let Some(quirks) = self.quirks {
+            chip.set_quirks(quirks);
+        }
+        let mut runner = Runner::new(CYCLES_PER_FRAME, FRAMES);
+        runner.set_keys(KeyScript::parse(self.keys).unwrap());
+        runner.run(&mut chip);
+        chip
+    }
+
+    fn golden_path(&self) -> PathBuf {
+        root()
+            .join("tests/golden")
+            .join(format!("{}.txt", self.name))
+    }
+
+    fn golden(&self) -> String {
+        fs::read_to_string(self.golden_path()).unwrap_or_default()
+    }
+}
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+fn check(case: Case) {
+    let chip = case.run();
+    let screen = export::to_text(chip.screen(), &export::ASCII_CHARS);
+    if env::var_os("CHIP8_UPDATE_GOLDEN").is_some() {
+        fs::write(case.golden_path(), screen).unwrap();
+        return;
+    }
+    if screen != case.golden() {
+        let dir = root().join("target/golden");
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join(format!("{}.txt", case.name)), &screen).unwrap();
+        Image::render(chip.screen(), 8, &export::DEFAULT_PALETTE)
+            .save_png(dir.join(format!("{}.png", case.name)))
+            .unwrap();
+        panic!(
+            "{} doesn't match {}, see target/golden/{}.txt",
+            case.name,
+            case.golden_path().display(),
+            case.name
+        );
+    }
+}
+
+#[test]
+fn own_opcodes() {
+    check(Case::new("own_opcodes", "own/opcodes.asm"));
+}
+
+#[test]
+fn own_flags() {
+    check(Case::new("own_flags", "own/flags.asm"));
+}
+
+#[test]
+fn own_quirks_vip() {
+    check(Case::new("own_quirks_vip", "own/quirks.asm").quirks(Quirks::cosmac_vip()));
+}
+
+#[test]
+fn own_quirks_schip() {
+    check(Case {
+        platform: Platform::SuperChip,
+        ..Case::new("own_quirks_schip", "own/quirks.asm")
+    });
+}
+
+#[test]
+fn own_quirks_xochip() {
+    check(Case {
+        platform: Platform::XoChip,
+        ..Case::new("own_quirks_xochip", "own/quirks.asm")
+    });
+}
+
+#[test]
+fn own_keypad() {
+    check(Case::new("own_keypad", "own/keypad.asm").keys("10:5 30:+7 60:-7"));
+}
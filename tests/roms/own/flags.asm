@@ -0,0 +1,115 @@
+; Checks VF after the arithmetic instructions, including when VF is also an
+; operand: the flag is always written last. Every check must show a tick.
+
+:alias result VB
+
+        ; 0: 8XY4 with and without carry
+        LD V0, 0xFF
+        LD V1, 0x01
+        ADD V0, V1
+        LD V2, VF
+        ADD V0, V1
+        LD V3, VF
+        LD result, 0
+        SE V2, 1
+        JP fail0
+        SE V3, 0
+        JP fail0
+        LD result, 1
+fail0:  CALL report
+
+        ; 1: 8XY5, VF is 1 without borrow
+        LD V0, 5
+        LD V1, 3
+        SUB V0, V1
+        LD V2, VF
+        SUB V0, V1
+        LD V3, VF
+        LD result, 0
+        SE V2, 1
+        JP fail1
+        SE V3, 0
+        JP fail1
+        LD result, 1
+fail1:  CALL report
+
+        ; 2: 8XY7, VF is 1 without borrow
+        LD V0, 3
+        LD V1, 5
+        SUBN V0, V1
+        LD V2, VF
+        LD V0, 5
+        LD V1, 3
+        SUBN V0, V1
+        LD V3, VF
+        LD result, 0
+        SE V2, 1
+        JP fail2
+        SE V3, 0
+        JP fail2
+        LD result, 1
+fail2:  CALL report
+
+        ; 3: 8XY6 and 8XYE shift the flag out
+        LD V0, 0b00000001
+        SHR V0, V0
+        LD V2, VF
+        LD V0, 0b10000000
+        SHL V0, V0
+        LD V3, VF
+        LD result, 0
+        SE V2, 1
+        JP fail3
+        SE V3, 1
+        JP fail3
+        LD result, 1
+fail3:  CALL report
+
+        ; 4: 8FY4 keeps the carry, not the sum
+        LD VF, 0xFF
+        LD V1, 0x03
+        ADD VF, V1
+        LD result, 0
+        SNE VF, 1
+        LD result, 1
+        CALL report
+
+        ; 5: 8FY5 keeps the borrow flag, not the difference
+        LD VF, 0x01
+        LD V1, 0x02
+        SUB VF, V1
+        LD result, 0
+        SNE VF, 0
+        LD result, 1
+        CALL report
+
+        ; 6: 8FY6 keeps the bit shifted out
+        LD VF, 0b00000010
+        SHR VF, VF
+        LD result, 0
+        SNE VF, 0
+        LD result, 1
+        CALL report
+
+        ; 7: DXYN sets VF on collision only, drawing twice leaves the
+        ; screen as it was
+        LD I, dot
+        LD V0, 60
+        LD V1, 28
+        DRW V0, V1, 1
+        LD V2, VF
+        DRW V0, V1, 1
+        LD V3, VF
+        LD result, 0
+        SE V2, 0
+        JP fail7
+        SE V3, 1
+        JP fail7
+        LD result, 1
+fail7:  CALL report
+
+end:    JP end
+
+dot:    db 0b10000000
+
+:include "report.asm"
@@ -0,0 +1,28 @@
+; Checks the keypad instructions. Expects key 5 to be pressed once, then key
+; 7 to be held and released. A check that doesn't pass never shows up.
+
+:alias result VB
+
+        ; 0: FX0A waits for a key and stores it
+        LD V0, K
+        LD result, 0
+        SNE V0, 5
+        LD result, 1
+        CALL report
+
+        ; 1: EX9E skips once the key is held
+        LD V0, 7
+wait1:  SKP V0
+        JP wait1
+        LD result, 1
+        CALL report
+
+        ; 2: EXA1 skips once the key is released
+wait2:  SKNP V0
+        JP wait2
+        LD result, 1
+        CALL report
+
+end:    JP end
+
+:include "report.asm"
@@ -0,0 +1,162 @@
+; Checks the result of the CHIP-8 instructions that compute something.
+; Every check must show a tick.
+
+:alias result VB
+
+        ; 0: 7XNN wraps around
+        LD V0, 0xF0
+        ADD V0, 0x20
+        LD result, 0
+        SNE V0, 0x10
+        LD result, 1
+        CALL report
+
+        ; 1: 3XNN skips when equal
+        LD result, 0
+        SE V0, 0x10
+        JP fail1
+        LD result, 1
+fail1:  CALL report
+
+        ; 2: 4XNN skips when not equal
+        LD result, 0
+        SNE V0, 0x11
+        JP fail2
+        LD result, 1
+fail2:  CALL report
+
+        ; 3: 5XY0 and 9XY0
+        LD V1, 0x10
+        LD V2, 0x11
+        LD result, 0
+        SE V0, V1
+        JP fail3
+        SNE V0, V2
+        JP fail3
+        LD result, 1
+fail3:  CALL report
+
+        ; 4: 8XY1, 8XY2 and 8XY3
+        LD V0, 0b1100
+        LD V1, 0b1010
+        LD V2, V0
+        OR V2, V1
+        LD V3, V0
+        AND V3, V1
+        LD V4, V0
+        XOR V4, V1
+        LD result, 0
+        SE V2, 0b1110
+        JP fail4
+        SE V3, 0b1000
+        JP fail4
+        SE V4, 0b0110
+        JP fail4
+        LD result, 1
+fail4:  CALL report
+
+        ; 5: 8XY4, 8XY5 and 8XY7
+        LD V0, 200
+        LD V1, 100
+        LD V2, V0
+        ADD V2, V1
+        LD V3, V0
+        SUB V3, V1
+        LD V4, V0
+        SUBN V4, V1
+        LD result, 0
+        SE V2, 44
+        JP fail5
+        SE V3, 100
+        JP fail5
+        SE V4, 156
+        JP fail5
+        LD result, 1
+fail5:  CALL report
+
+        ; 6: 8XY6 and 8XYE on VX, which every quirk agrees on
+        LD V0, 0b10000011
+        SHR V0, V0
+        LD V1, 0b10000011
+        SHL V1, V1
+        LD result, 0
+        SE V0, 0b01000001
+        JP fail6
+        SE V1, 0b00000110
+        JP fail6
+        LD result, 1
+fail6:  CALL report
+
+        ; 7: ANNN, FX1E and FX55 / FX65
+        LD I, scratch
+        LD V0, 1
+        ADD I, V0
+        LD V0, 0xAB
+        LD V1, 0xCD
+        LD [I], V1
+        LD I, scratch
+        LD V2, [I]
+        LD result, 0
+        SE V0, 0x00
+        JP fail7
+        SE V1, 0xAB
+        JP fail7
+        SE V2, 0xCD
+        JP fail7
+        LD result, 1
+fail7:  CALL report
+
+        ; 8: FX33
+        LD V0, 254
+        LD I, scratch
+        LD B, V0
+        LD I, scratch
+        LD V2, [I]
+        LD result, 0
+        SE V0, 2
+        JP fail8
+        SE V1, 5
+        JP fail8
+        SE V2, 4
+        JP fail8
+        LD result, 1
+fail8:  CALL report
+
+        ; 9: 2NNN and 00EE
+        LD V0, 0
+        CALL increment
+        CALL increment
+        LD result, 0
+        SNE V0, 2
+        LD result, 1
+        CALL report
+
+        ; A: FX29 points at the font
+        LD V0, 0xA
+        LD F, V0
+        LD V0, [I]
+        LD result, 0
+        SNE V0, 0xF0
+        LD result, 1
+        CALL report
+
+        ; B: FX15 / FX07, the timer may have ticked in between
+        LD V0, 30
+        LD DT, V0
+        LD V1, DT
+        LD result, 0
+        SNE V1, 30
+        LD result, 1
+        SNE V1, 29
+        LD result, 1
+        CALL report
+
+end:    JP end
+
+increment:
+        ADD V0, 1
+        RET
+
+scratch: db 0, 0, 0
+
+:include "report.asm"
@@ -0,0 +1,87 @@
+; Shows which behaviour the interpreter has for each quirk, see `Quirks`. A
+; tick means the quirk is set, so the result depends on the profile.
+
+:alias result VB
+
+        ; 0: shift, 8XY6 shifts VX instead of VY
+        LD V0, 0b0100
+        LD V1, 0b0010
+        SHR V0, V1
+        LD result, 0
+        SNE V0, 0b0010
+        LD result, 1
+        CALL report
+
+        ; 1: load_store, FX55 moves I
+        LD I, scratch
+        LD V0, 0xAA
+        LD [I], V0
+        LD V0, [I]
+        LD result, 0
+        SE V0, 0xAA
+        LD result, 1
+        CALL report
+
+        ; 2: jump, BXNN adds VX instead of V0. X is the page of `table`,
+        ; which is 2 as long as it stays below 0x300.
+        LD V0, 0
+        LD V2, 4
+        LD result, 0
+        JP V0, table
+table:  JP done2
+        JP done2
+        LD result, 1
+done2:  CALL report
+
+        ; 3: clipping, sprites don't wrap around the edges. Draws on the
+        ; bottom row, away from the results, and erases it afterwards.
+        LD I, wide
+        LD V0, 60
+        LD V1, 31
+        DRW V0, V1, 1
+        LD V0, 0
+        DRW V0, V1, 1
+        LD result, 0
+        SNE VF, 0
+        LD result, 1
+        DRW V0, V1, 1
+        LD V0, 60
+        DRW V0, V1, 1
+        CALL report
+
+        ; 4: vf_reset, 8XY1 resets VF
+        LD VF, 1
+        LD V0, 0
+        OR V0, V0
+        LD result, 0
+        SNE VF, 0
+        LD result, 1
+        CALL report
+
+        ; 5: display_wait, DXYN waits for the next timer tick. Waits for a
+        ; tick to start, then counts the sprites drawn before the next one.
+        ; The sprite is blank so the screen is left alone.
+        LD V0, 2
+        LD DT, V0
+sync5:  LD V0, DT
+        SE V0, 1
+        JP sync5
+        LD I, blank
+        LD V1, 0
+draw5:  DRW V0, V0, 1
+        ADD V1, 1
+        LD V2, DT
+        SE V2, 0
+        JP draw5
+        LD result, 0
+        SNE V1, 1
+        LD result, 1
+        CALL report
+
+end:    JP end
+
+scratch: db 0, 0
+wide:    db 0xFF
+blank:   db 0
+
+:include "report.asm"
@@ -0,0 +1,29 @@
+; Shared by the test ROMs: draws the result of each check as its number
+; followed by a tick or a cross, four checks per row.
+;
+; VB holds the result of the check (1 if it passed), VC to VE are kept by
+; `report` between calls. The checks are free to use V0 to VA and VF.
+
+:alias result VB
+:alias row VC
+:alias column VD
+:alias check VE
+
+report:
+        LD F, check
+        DRW column, row, 5
+        ADD column, 5
+        LD I, cross
+        SE result, 0
+        LD I, tick
+        DRW column, row, 5
+        ADD column, 11
+        ADD check, 1
+        SE column, 64
+        RET
+        LD column, 0
+        ADD row, 6
+        RET
+
+tick:   db 0b00000000, 0b00000001, 0b00000010, 0b10100100, 0b01000000
+cross:  db 0b10001000, 0b01010000, 0b00100000, 0b01010000, 0b10001000